@@ -1,7 +1,12 @@
-use std::collections::HashMap;
+use intcode::search::Search;
+use intcode::symbolic::{Executor, Variable};
+use intcode::{Budget, State, Vm};
 use std::fs::File;
 use std::io::Read;
 
+const BUDGET: u64 = 100_000;
+const TARGET: i64 = 19690720;
+
 fn main() {
     part1();
     part2();
@@ -14,12 +19,13 @@ fn part1() {
         .read_to_string(&mut input)
         .unwrap();
 
-    let mut memory = ingest(input.trim_end());
+    let mut vm: Vm<i64> = Vm::parse(input.trim_end());
 
-    *memory.get_mut(&1).unwrap() = 12;
-    *memory.get_mut(&2).unwrap() = 2;
+    vm.set(1, 12);
+    vm.set(2, 2);
+    vm.execute().unwrap();
 
-    println!("{}", execute(memory).get(&0).unwrap());
+    println!("{}", vm.get(0));
 }
 
 fn part2() {
@@ -29,60 +35,40 @@ fn part2() {
         .read_to_string(&mut input)
         .unwrap();
 
-    let memory = ingest(input.trim_end());
-
-    for noun in 0..99 {
-        for verb in 0..99 {
-            let mut current_memory = memory.clone();
-
-            *current_memory.get_mut(&1).unwrap() = noun;
-            *current_memory.get_mut(&2).unwrap() = verb;
-
-            if *execute(current_memory).get(&0).unwrap() == 19690720 {
-                println!("{}{}", noun, verb);
-                break;
-            }
-        }
+    let program: Vm<i64> = Vm::parse(input.trim_end());
+
+    // Anything short of a solution from the symbolic run falls back to trying every input.
+    let found = solve(&program).or_else(|| {
+        Search::new(program)
+            .vary(1, 0..=99)
+            .vary(2, 0..=99)
+            .budget(Budget::new().steps(BUDGET))
+            .first(|vm, _| vm.get(0) == TARGET)
+            .unwrap()
+            .map(|found| (found[0], found[1]))
+    });
+
+    if let Some((noun, verb)) = found {
+        println!("{}{}", noun, verb);
     }
 }
 
-fn ingest(input: &str) -> HashMap<usize, usize> {
-    input
-        .split(',')
-        .map(|x| x.parse::<usize>().unwrap())
-        .enumerate()
-        .collect()
-}
-
-fn execute(mut memory: HashMap<usize, usize>) -> HashMap<usize, usize> {
-    let mut counter: usize = 0;
-    while let Some(&opcode) = memory.get(&counter) {
-        if opcode == 99 {
-            break;
-        }
-
-        let lhs = get_value(&memory, get_value(&memory, counter + 1));
-        let rhs = get_value(&memory, get_value(&memory, counter + 2));
-        let destination = get_value(&memory, counter + 3);
-
-        match opcode {
-            1 => {
-                memory.insert(destination, lhs + rhs);
-            }
-            2 => {
-                memory.insert(destination, lhs * rhs);
-            }
-            _ => break,
-        }
-
-        counter += 4;
+/// Works out the noun and verb from the expression the program builds for position 0, which
+/// is linear in both for these programs. Returns `None` if it isn't, or the run doesn't halt.
+fn solve(program: &Vm<i64>) -> Option<(i64, i64)> {
+    let mut executor = Executor::new(program).ok()?;
+    executor.variable(1);
+    executor.variable(2);
+    if executor.execute_within(Budget::new().steps(BUDGET)).ok()? != State::Halted {
+        return None;
     }
 
-    memory
-}
+    let ranges = [(Variable::Cell(1), 0..=99), (Variable::Cell(2), 0..=99)];
+    let solutions = executor.get(0).solve(TARGET.into(), &ranges).ok()?;
 
-fn get_value(memory: &HashMap<usize, usize>, address: usize) -> usize {
-    memory.get(&address).unwrap().clone()
+    solutions
+        .first()
+        .map(|found| (found[0] as i64, found[1] as i64))
 }
 
 #[cfg(test)]
@@ -92,12 +78,29 @@ mod test {
     #[test]
     fn test_execute() {
         let input = "1,9,10,3,2,3,11,0,99,30,40,50";
-        dbg!(execute(ingest(input)));
+        let mut vm = Vm::from(input);
+        vm.execute().unwrap();
+
+        assert_eq!(vm.get(0), 3500);
     }
 
     #[test]
     fn test_execute_2() {
         let input = "1,1,1,4,99,5,6,0,99";
-        dbg!(execute(ingest(input)));
+        let mut vm = Vm::from(input);
+        vm.execute().unwrap();
+
+        assert_eq!(vm.get(0), 30);
+    }
+
+    #[test]
+    fn test_solve_falls_back() {
+        // Position 0 is the product of the noun and verb, which isn't linear.
+        let program: Vm<i64> = Vm::parse("2,0,0,0,99");
+        assert_eq!(solve(&program), None);
+
+        // Loops forever, so the symbolic run never halts.
+        let program: Vm<i64> = Vm::parse("1105,1,0,99");
+        assert_eq!(solve(&program), None);
     }
 }
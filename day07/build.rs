@@ -0,0 +1,15 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=input.txt");
+
+    let input = fs::read_to_string("input.txt").unwrap();
+    let source = intcode::transpile::transpile(&intcode::parse::<i128>(&input)).unwrap();
+    fs::write(
+        Path::new(&env::var("OUT_DIR").unwrap()).join("amplifier.rs"),
+        source,
+    )
+    .unwrap();
+}
@@ -1,17 +1,9 @@
-use crate::vm::Vm;
+use intcode::device::TextOutput;
+use intcode::Vm;
 use std::fs::File;
 use std::io::Read;
 
-mod vm;
-
 fn main() {
-    fern::Dispatch::new()
-        .format(|out, message, _record| out.finish(format_args!("{}", message,)))
-        .level(log::LevelFilter::Debug)
-        .chain(std::io::stdout())
-        .apply()
-        .unwrap();
-
     let mut input = String::new();
     File::open("input.txt")
         .unwrap()
@@ -24,18 +16,12 @@ fn main() {
 
 fn part1(input: &str) {
     let mut vm = Vm::from(input);
-    vm.add_input(1);
-
-    while let Some(output) = vm.execute() {
-        println!("{}", output);
-    }
+    vm.run(&mut Some(1.into()), &mut TextOutput::stdout())
+        .unwrap();
 }
 
 fn part2(input: &str) {
     let mut vm = Vm::from(input);
-    vm.add_input(2);
-
-    while let Some(output) = vm.execute() {
-        println!("{}", output);
-    }
+    vm.run(&mut Some(2.into()), &mut TextOutput::stdout())
+        .unwrap();
 }
@@ -0,0 +1,116 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use intcode::{parse, DefaultWord, Memory, PagedMemory, State, Vm, Word};
+use std::collections::HashMap;
+
+const BOOST: &str = include_str!("../../day09/input.txt");
+const AMPLIFIERS: &str = include_str!("../../day07/input.txt");
+
+fn boost<W: Word, M: Memory<W>>(program: &[W]) -> W {
+    let mut vm = Vm::new(M::from_program(program.to_vec()));
+    vm.add_input(W::from(2));
+
+    match vm.execute().unwrap() {
+        State::Output(value) => value,
+        state => panic!("unexpected state {:?}", state),
+    }
+}
+
+fn permutations(phases: Vec<i32>) -> Vec<Vec<i32>> {
+    if phases.len() <= 1 {
+        return vec![phases];
+    }
+
+    let mut result = Vec::new();
+    for i in 0..phases.len() {
+        let mut rest = phases.clone();
+        let first = rest.remove(i);
+        for mut permutation in permutations(rest) {
+            permutation.insert(0, first);
+            result.push(permutation);
+        }
+    }
+
+    result
+}
+
+fn feedback_loop<W: Word, M: Memory<W>>(program: &[W]) -> W {
+    let mut max = W::from(0);
+
+    for phases in permutations((5..10).collect()) {
+        let mut amps: Vec<Vm<W, M>> = phases
+            .iter()
+            .map(|&phase| {
+                let mut amp = Vm::new(M::from_program(program.to_vec()));
+                amp.add_input(W::from(phase));
+                amp
+            })
+            .collect();
+        amps[0].add_input(W::from(0));
+
+        let mut signal = W::from(0);
+        'ring: loop {
+            for i in 0..amps.len() {
+                match amps[i].execute().unwrap() {
+                    State::Output(value) => {
+                        let next = (i + 1) % amps.len();
+                        if next == 0 {
+                            signal = value.clone();
+                        }
+                        amps[next].add_input(value);
+                    }
+                    _ => break 'ring,
+                }
+            }
+        }
+
+        max = max.max(signal);
+    }
+
+    max
+}
+
+fn bench_boost(c: &mut Criterion) {
+    let program: Vec<DefaultWord> = parse(BOOST);
+    let mut group = c.benchmark_group("day09 part 2");
+    group.sample_size(20);
+
+    group.bench_function("hash map", |b| {
+        b.iter(|| boost::<_, HashMap<usize, DefaultWord>>(&program))
+    });
+    group.bench_function("paged", |b| b.iter(|| boost::<_, PagedMemory>(&program)));
+    group.finish();
+}
+
+fn bench_feedback_loop(c: &mut Criterion) {
+    let program: Vec<DefaultWord> = parse(AMPLIFIERS);
+    let mut group = c.benchmark_group("day07 feedback loop");
+
+    group.bench_function("hash map", |b| {
+        b.iter(|| feedback_loop::<_, HashMap<usize, DefaultWord>>(&program))
+    });
+    group.bench_function("paged", |b| {
+        b.iter(|| feedback_loop::<_, PagedMemory>(&program))
+    });
+    group.finish();
+}
+
+fn bench_word_width(c: &mut Criterion) {
+    let mut group = c.benchmark_group("day07 word width");
+
+    let program: Vec<i32> = parse(AMPLIFIERS);
+    group.bench_function("i32", |b| {
+        b.iter(|| feedback_loop::<_, PagedMemory<_>>(&program))
+    });
+    let program: Vec<i64> = parse(AMPLIFIERS);
+    group.bench_function("i64", |b| {
+        b.iter(|| feedback_loop::<_, PagedMemory<_>>(&program))
+    });
+    let program: Vec<i128> = parse(AMPLIFIERS);
+    group.bench_function("i128", |b| {
+        b.iter(|| feedback_loop::<_, PagedMemory<_>>(&program))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_boost, bench_feedback_loop, bench_word_width);
+criterion_main!(benches);
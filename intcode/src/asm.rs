@@ -0,0 +1,430 @@
+use crate::disasm;
+use crate::vm::parameter_count;
+use crate::word::Word;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AsmError {
+    UnknownMnemonic {
+        line: usize,
+        mnemonic: String,
+    },
+    InvalidOperand {
+        line: usize,
+        operand: String,
+    },
+    OperandCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    ImmediateWrite {
+        line: usize,
+    },
+    DuplicateLabel {
+        line: usize,
+        label: String,
+    },
+    UndefinedLabel {
+        line: usize,
+        label: String,
+    },
+    AddressMismatch {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsmError::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "line {}: unknown mnemonic {}", line, mnemonic)
+            }
+            AsmError::InvalidOperand { line, operand } => {
+                write!(f, "line {}: invalid operand {}", line, operand)
+            }
+            AsmError::OperandCount {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected {} operands, found {}",
+                line, expected, found
+            ),
+            AsmError::ImmediateWrite { line } => {
+                write!(f, "line {}: write operand in immediate mode", line)
+            }
+            AsmError::DuplicateLabel { line, label } => {
+                write!(f, "line {}: duplicate label {}", line, label)
+            }
+            AsmError::UndefinedLabel { line, label } => {
+                write!(f, "line {}: undefined label {}", line, label)
+            }
+            AsmError::AddressMismatch {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: address {} does not match assembled address {}",
+                line, expected, found
+            ),
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Clone, Debug)]
+enum Value<W> {
+    Literal(W),
+    Label(String, i128),
+}
+
+#[derive(Clone, Debug)]
+struct Operand<W> {
+    mode: i32,
+    value: Value<W>,
+}
+
+#[derive(Clone, Debug)]
+enum Item<W> {
+    Instruction {
+        line: usize,
+        operation: usize,
+        operands: Vec<Operand<W>>,
+    },
+    Data {
+        line: usize,
+        values: Vec<Value<W>>,
+    },
+}
+
+/// Assembles a listing into a program. Each line is an optional `label:` followed by a
+/// mnemonic and its operands (`[pos]`, `#imm` or `rb+off`) or a `data` directive with a
+/// comma-separated list of values. Labels may appear wherever a number can, optionally with a
+/// `+n`/`-n` offset, and evaluate to the address they mark. Numeric labels such as the
+/// `0004:` prefixes of a disassembly are checked against the assembled address. Everything
+/// after a `;` is a comment.
+pub fn assemble<W: Word>(source: &str) -> Result<Vec<W>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut items = Vec::new();
+    let mut address = 0;
+
+    for (index, raw) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = raw.split(';').next().unwrap().trim();
+
+        if let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            text = text[colon + 1..].trim();
+
+            if let Ok(expected) = label.parse::<usize>() {
+                if expected != address {
+                    return Err(AsmError::AddressMismatch {
+                        line,
+                        expected,
+                        found: address,
+                    });
+                }
+            } else if !is_identifier(label) {
+                return Err(AsmError::InvalidOperand {
+                    line,
+                    operand: label.to_string(),
+                });
+            } else if labels.insert(label.to_string(), address).is_some() {
+                return Err(AsmError::DuplicateLabel {
+                    line,
+                    label: label.to_string(),
+                });
+            }
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = match text.find(char::is_whitespace) {
+            Some(split) => (&text[..split], text[split..].trim()),
+            None => (text, ""),
+        };
+        let arguments: Vec<&str> = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',').map(str::trim).collect()
+        };
+
+        if mnemonic == "data" {
+            let values = arguments
+                .iter()
+                .map(|argument| parse_value(argument, line))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            address += values.len();
+            items.push(Item::Data { line, values });
+            continue;
+        }
+
+        let operation = match disasm::operation(mnemonic) {
+            Some(operation) => operation,
+            None => {
+                return Err(AsmError::UnknownMnemonic {
+                    line,
+                    mnemonic: mnemonic.to_string(),
+                })
+            }
+        };
+
+        let expected = parameter_count(operation).unwrap();
+        if arguments.len() != expected {
+            return Err(AsmError::OperandCount {
+                line,
+                expected,
+                found: arguments.len(),
+            });
+        }
+
+        let operands = arguments
+            .iter()
+            .map(|argument| parse_operand(argument, line))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (parameter, operand) in operands.iter().enumerate() {
+            if operand.mode == 1 && writes(operation, parameter) {
+                return Err(AsmError::ImmediateWrite { line });
+            }
+        }
+
+        address += 1 + operands.len();
+        items.push(Item::Instruction {
+            line,
+            operation,
+            operands,
+        });
+    }
+
+    let mut program = Vec::with_capacity(address);
+    for item in items {
+        match item {
+            Item::Instruction {
+                line,
+                operation,
+                operands,
+            } => {
+                let modes = operands
+                    .iter()
+                    .rev()
+                    .fold(0, |modes, operand| modes * 10 + operand.mode);
+                program.push(W::from(modes * 100 + operation as i32));
+
+                for operand in operands {
+                    program.push(resolve(&operand.value, &labels, line)?);
+                }
+            }
+            Item::Data { line, values } => {
+                for value in values {
+                    program.push(resolve(&value, &labels, line)?);
+                }
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+pub fn emit<W: Word>(program: &[W]) -> String {
+    program
+        .iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+pub(crate) fn writes(operation: usize, parameter: usize) -> bool {
+    match operation {
+        1 | 2 | 7 | 8 => parameter == 2,
+        3 => parameter == 0,
+        _ => false,
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn parse_operand<W: Word>(text: &str, line: usize) -> Result<Operand<W>, AsmError> {
+    let (mode, value) = if let Some(value) = text.strip_prefix('#') {
+        (1, parse_value(value, line)?)
+    } else if let Some(value) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        (0, parse_value(value, line)?)
+    } else if text.starts_with("rb+") || text.starts_with("rb-") {
+        (2, parse_value(&text[2..], line)?)
+    } else {
+        return Err(AsmError::InvalidOperand {
+            line,
+            operand: text.to_string(),
+        });
+    };
+
+    Ok(Operand { mode, value })
+}
+
+fn parse_value<W: Word>(text: &str, line: usize) -> Result<Value<W>, AsmError> {
+    let text = text.trim();
+    if let Ok(literal) = text.trim_start_matches('+').parse() {
+        return Ok(Value::Literal(literal));
+    }
+
+    let (label, offset) = match text.find(['+', '-']) {
+        Some(split) => match text[split..].trim_start_matches('+').trim().parse() {
+            Ok(offset) => (text[..split].trim(), offset),
+            Err(_) => ("", 0),
+        },
+        None => (text, 0),
+    };
+
+    if is_identifier(label) {
+        Ok(Value::Label(label.to_string(), offset))
+    } else {
+        Err(AsmError::InvalidOperand {
+            line,
+            operand: text.to_string(),
+        })
+    }
+}
+
+fn resolve<W: Word>(
+    value: &Value<W>,
+    labels: &HashMap<String, usize>,
+    line: usize,
+) -> Result<W, AsmError> {
+    match value {
+        Value::Literal(literal) => Ok(literal.clone()),
+        Value::Label(label, offset) => match labels.get(label) {
+            Some(&address) => {
+                W::try_from(address as i128 + offset).map_err(|_| AsmError::InvalidOperand {
+                    line,
+                    operand: label.clone(),
+                })
+            }
+            None => Err(AsmError::UndefinedLabel {
+                line,
+                label: label.clone(),
+            }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::disasm::listing;
+    use crate::vm::{parse, State, Vm};
+    use crate::word::{w, DefaultWord};
+
+    #[test]
+    fn test_modes() {
+        let program: Vec<DefaultWord> =
+            assemble::<DefaultWord>("add [4], #3, [4]\nadd rb-1, rb+2, rb+3\nhlt").unwrap();
+
+        assert_eq!(emit(&program), "1001,4,3,4,22201,-1,2,3,99");
+    }
+
+    #[test]
+    fn test_labels() {
+        let source = "
+            ; count down from the input, printing each value
+                    in [counter]
+            loop:   out [counter]
+                    add [counter], #-1, [counter]
+                    jnz [counter], #loop
+                    hlt
+            counter: data 0
+        ";
+        let mut vm = Vm::from(emit(&assemble::<DefaultWord>(source).unwrap()));
+        vm.add_input(w(3));
+
+        assert_eq!(vm.execute(), Ok(State::Output(w(3))));
+        assert_eq!(vm.execute(), Ok(State::Output(w(2))));
+        assert_eq!(vm.execute(), Ok(State::Output(w(1))));
+        assert_eq!(vm.execute(), Ok(State::Halted));
+    }
+
+    #[test]
+    fn test_label_offset() {
+        let program: Vec<DefaultWord> =
+            assemble::<DefaultWord>("target: add #1, #2, [target+3]\nhlt").unwrap();
+
+        assert_eq!(emit(&program), "1101,1,2,3,99");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let program: Vec<DefaultWord> = parse(input);
+
+        assert_eq!(
+            assemble::<DefaultWord>(&listing(&program)).unwrap(),
+            program
+        );
+    }
+
+    #[test]
+    fn test_round_trip_with_data() {
+        let program: Vec<DefaultWord> = parse("1,9,10,3,2,3,11,0,99,30,40,50");
+
+        assert_eq!(
+            assemble::<DefaultWord>(&listing(&program)).unwrap(),
+            program
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            assemble::<DefaultWord>("nop"),
+            Err(AsmError::UnknownMnemonic {
+                line: 1,
+                mnemonic: "nop".to_string()
+            })
+        );
+        assert_eq!(
+            assemble::<DefaultWord>("hlt\nadd #1, #2, #3"),
+            Err(AsmError::ImmediateWrite { line: 2 })
+        );
+        assert_eq!(
+            assemble::<DefaultWord>("jnz #1"),
+            Err(AsmError::OperandCount {
+                line: 1,
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(
+            assemble::<DefaultWord>("jz #0, #end"),
+            Err(AsmError::UndefinedLabel {
+                line: 1,
+                label: "end".to_string()
+            })
+        );
+        assert_eq!(
+            assemble::<DefaultWord>("0002: hlt"),
+            Err(AsmError::AddressMismatch {
+                line: 1,
+                expected: 2,
+                found: 0
+            })
+        );
+    }
+}
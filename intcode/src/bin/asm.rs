@@ -0,0 +1,23 @@
+use intcode::{asm, DefaultWord};
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::process;
+
+fn main() {
+    let path = env::args().nth(1).expect("usage: asm <source>");
+
+    let mut source = String::new();
+    File::open(path)
+        .unwrap()
+        .read_to_string(&mut source)
+        .unwrap();
+
+    match asm::assemble::<DefaultWord>(&source) {
+        Ok(program) => println!("{}", asm::emit(&program)),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
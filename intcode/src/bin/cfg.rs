@@ -0,0 +1,22 @@
+use intcode::cfg::Cfg;
+use intcode::DefaultWord;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "input.txt".to_string());
+
+    let mut input = String::new();
+    File::open(path)
+        .unwrap()
+        .read_to_string(&mut input)
+        .unwrap();
+
+    print!(
+        "{}",
+        Cfg::<DefaultWord>::build(&intcode::parse(&input)).to_dot()
+    );
+}
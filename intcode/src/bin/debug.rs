@@ -0,0 +1,24 @@
+use intcode::debugger::Debugger;
+use intcode::Vm;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let path = args.next().expect("usage: debug <program> [input...]");
+
+    let mut input = String::new();
+    File::open(path)
+        .unwrap()
+        .read_to_string(&mut input)
+        .unwrap();
+
+    let mut vm = Vm::from(input);
+    for arg in args {
+        vm.add_input(arg.parse().unwrap());
+    }
+
+    let stdin = io::stdin();
+    Debugger::new(vm).run(stdin.lock(), io::stdout()).unwrap();
+}
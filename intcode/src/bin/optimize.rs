@@ -0,0 +1,23 @@
+use intcode::asm::emit;
+use intcode::optimize::optimize;
+use intcode::DefaultWord;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "input.txt".to_string());
+
+    let mut input = String::new();
+    File::open(path)
+        .unwrap()
+        .read_to_string(&mut input)
+        .unwrap();
+
+    let program: Vec<DefaultWord> = intcode::parse(&input);
+    let optimized = optimize(&program);
+    eprintln!("{} cells -> {} cells", program.len(), optimized.len());
+    println!("{}", emit(&optimized));
+}
@@ -0,0 +1,33 @@
+use intcode::device::TextOutput;
+use intcode::{parse, DefaultWord, Vm};
+use std::collections::VecDeque;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+
+const USAGE: &str = "usage: profile [--annotate] <program> [input...]";
+
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+    let annotate = args.next_if_eq("--annotate").is_some();
+    let path = args.next().expect(USAGE);
+
+    let mut input = String::new();
+    File::open(path)
+        .unwrap()
+        .read_to_string(&mut input)
+        .unwrap();
+
+    let program: Vec<DefaultWord> = parse(&input);
+    let mut vm = Vm::from(input);
+    let mut inputs: VecDeque<DefaultWord> = args.map(|arg| arg.parse().unwrap()).collect();
+
+    vm.enable_profile();
+    vm.run(&mut inputs, &mut TextOutput::stdout()).unwrap();
+
+    let profile = vm.take_profile();
+    println!("{}", profile.report(&program, 20));
+    if annotate {
+        print!("{}", profile.annotate(&program));
+    }
+}
@@ -0,0 +1,31 @@
+use intcode::trace::Tracer;
+use intcode::{State, Vm};
+use std::env;
+use std::fs::File;
+use std::io::Read;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let path = args
+        .next()
+        .expect("usage: trace <program> <trace> [input...]");
+    let trace = args
+        .next()
+        .expect("usage: trace <program> <trace> [input...]");
+
+    let mut input = String::new();
+    File::open(path)
+        .unwrap()
+        .read_to_string(&mut input)
+        .unwrap();
+
+    let mut vm = Vm::from(input);
+    for arg in args {
+        vm.add_input(arg.parse().unwrap());
+    }
+
+    let mut tracer = Tracer::create(trace).unwrap();
+    while let State::Output(output) = tracer.execute(&mut vm).unwrap() {
+        println!("{}", output);
+    }
+}
@@ -0,0 +1,26 @@
+use intcode::transpile::transpile;
+use intcode::DefaultWord;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::process;
+
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "input.txt".to_string());
+
+    let mut input = String::new();
+    File::open(path)
+        .unwrap()
+        .read_to_string(&mut input)
+        .unwrap();
+
+    match transpile(&intcode::parse::<DefaultWord>(&input)) {
+        Ok(source) => print!("{}", source),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::memory::Memory;
+use crate::vm::{State, Vm};
+use crate::word::Word;
+use crate::VmError;
+use std::time::{Duration, Instant};
+
+/// How many instructions run between reads of the clock.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Limits for a single `Vm::execute_within` call. The default is unlimited.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Budget {
+    steps: Option<u64>,
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    pub fn new() -> Self {
+        Budget::default()
+    }
+
+    /// Stops after `steps` instructions.
+    pub fn steps(mut self, steps: u64) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stops once `timeout` has passed, counted from when the budget is built.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.deadline(Instant::now() + timeout)
+    }
+
+    /// Whether a run that has executed `executed` instructions has to stop.
+    pub(crate) fn exhausted(&self, executed: u64) -> bool {
+        if self.steps.is_some_and(|steps| executed >= steps) {
+            return true;
+        }
+
+        executed.is_multiple_of(CLOCK_INTERVAL)
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+impl<W: Word, M: Memory<W>> Vm<W, M> {
+    /// Like `execute`, but gives up with `State::BudgetExhausted` when `budget` runs out. The
+    /// VM is left at the next instruction, so calling again carries on where it stopped.
+    pub fn execute_within(&mut self, budget: Budget) -> Result<State<W>, VmError<W>> {
+        let mut executed = 0;
+
+        loop {
+            if budget.exhausted(executed) {
+                return Ok(State::BudgetExhausted);
+            }
+
+            if let Some(state) = self.step()? {
+                return Ok(state);
+            }
+            executed += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::word::w;
+
+    #[test]
+    fn test_steps() {
+        let mut vm = Vm::from("1101,0,0,11,1101,0,0,11,104,7,99");
+
+        assert_eq!(
+            vm.execute_within(Budget::new().steps(1)),
+            Ok(State::BudgetExhausted)
+        );
+        assert_eq!(vm.pointer(), 4);
+        assert_eq!(
+            vm.execute_within(Budget::new().steps(2)),
+            Ok(State::Output(w(7)))
+        );
+        assert_eq!(vm.execute_within(Budget::new()), Ok(State::Halted));
+    }
+
+    #[test]
+    fn test_infinite_loop() {
+        let mut vm = Vm::from("1105,1,0");
+
+        assert_eq!(
+            vm.execute_within(Budget::new().steps(1000)),
+            Ok(State::BudgetExhausted)
+        );
+        assert_eq!(vm.steps(), 1000);
+        assert_eq!(
+            vm.execute_within(Budget::new().timeout(Duration::from_millis(10))),
+            Ok(State::BudgetExhausted)
+        );
+    }
+}
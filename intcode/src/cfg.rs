@@ -0,0 +1,256 @@
+use crate::disasm::{self, Line, Operand};
+use crate::word::{DefaultWord, Word};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Edge {
+    /// Falls through to the instruction that follows.
+    Next(usize),
+    /// An immediate jump target.
+    Jump(usize),
+}
+
+impl Edge {
+    pub fn target(self) -> usize {
+        match self {
+            Edge::Next(target) | Edge::Jump(target) => target,
+        }
+    }
+}
+
+/// A run of instructions that is only entered at the top and only left at the bottom.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Block<W = DefaultWord> {
+    pub start: usize,
+    pub instructions: Vec<Line<W>>,
+    pub successors: Vec<Edge>,
+    /// Whether the block ends in a jump whose target is only known at run time.
+    pub indirect: bool,
+}
+
+impl<W> Block<W> {
+    /// The address just past the last instruction.
+    pub fn end(&self) -> usize {
+        match self.instructions.last() {
+            Some(Line::Instruction {
+                address, operands, ..
+            }) => address + 1 + operands.len(),
+            _ => self.start,
+        }
+    }
+}
+
+/// The basic blocks reachable from address 0. Like `disasm::disassemble`, only immediate jump
+/// targets are followed, and the program is assumed not to modify its own code.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cfg<W = DefaultWord> {
+    pub blocks: BTreeMap<usize, Block<W>>,
+}
+
+impl<W: Word> Cfg<W> {
+    pub fn build(program: &[W]) -> Self {
+        let mut code = BTreeSet::new();
+        let mut leaders = BTreeSet::new();
+        let mut pending = vec![0];
+        leaders.insert(0);
+
+        while let Some(address) = pending.pop() {
+            if code.contains(&address) {
+                continue;
+            }
+            let line = match disasm::decode(program, address) {
+                Some(line) => line,
+                None => continue,
+            };
+            code.insert(address);
+
+            let (successors, _) = exits(&line);
+            for edge in successors {
+                if ends_block(&line) {
+                    leaders.insert(edge.target());
+                }
+                pending.push(edge.target());
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in leaders.iter().filter(|start| code.contains(start)) {
+            let mut block = Block {
+                start,
+                instructions: Vec::new(),
+                successors: Vec::new(),
+                indirect: false,
+            };
+
+            let mut address = start;
+            while let Some(line) = disasm::decode(program, address) {
+                let (successors, indirect) = exits(&line);
+                let end = ends_block(&line);
+                block.instructions.push(line);
+
+                if end {
+                    block.successors = successors;
+                    block.indirect = indirect;
+                    break;
+                }
+
+                address = block.end();
+                if leaders.contains(&address) {
+                    block.successors = successors;
+                    break;
+                }
+            }
+
+            blocks.insert(start, block);
+        }
+
+        Cfg { blocks }
+    }
+
+    pub fn block(&self, start: usize) -> Option<&Block<W>> {
+        self.blocks.get(&start)
+    }
+
+    /// Renders the graph in Graphviz's DOT language. Taken jumps are drawn in bold, and
+    /// indirect jumps as dashed edges to a `?` node.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+
+        for block in self.blocks.values() {
+            let label: String = block
+                .instructions
+                .iter()
+                .map(|line| format!("{}\\l", line))
+                .collect();
+            writeln!(dot, "    b{} [label=\"{}\"];", block.start, label).unwrap();
+        }
+
+        for block in self.blocks.values() {
+            for edge in &block.successors {
+                match edge {
+                    Edge::Next(target) => writeln!(dot, "    b{} -> b{};", block.start, target),
+                    Edge::Jump(target) => {
+                        writeln!(dot, "    b{} -> b{} [style=bold];", block.start, target)
+                    }
+                }
+                .unwrap();
+            }
+            if block.indirect {
+                writeln!(dot, "    b{} -> indirect [style=dashed];", block.start).unwrap();
+            }
+        }
+
+        if self.blocks.values().any(|block| block.indirect) {
+            dot.push_str("    indirect [label=\"?\", shape=circle];\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn ends_block<W>(line: &Line<W>) -> bool {
+    matches!(
+        line,
+        Line::Instruction {
+            mnemonic: "jnz" | "jz" | "hlt",
+            ..
+        }
+    )
+}
+
+/// Where execution can go after `line`, and whether it may also jump somewhere unknown.
+fn exits<W: Word>(line: &Line<W>) -> (Vec<Edge>, bool) {
+    let (address, mnemonic, operands) = match line {
+        Line::Instruction {
+            address,
+            mnemonic,
+            operands,
+        } => (*address, *mnemonic, operands),
+        Line::Data { .. } => return (Vec::new(), false),
+    };
+    let next = address + 1 + operands.len();
+
+    match (mnemonic, operands.as_slice()) {
+        ("hlt", _) => (Vec::new(), false),
+        ("jnz", [condition, target]) | ("jz", [condition, target]) => {
+            let jumps = match condition {
+                Operand::Immediate(value) => Some(value.is_zero() != (mnemonic == "jnz")),
+                _ => None,
+            };
+
+            let mut successors = Vec::new();
+            let mut indirect = false;
+            if jumps != Some(false) {
+                match target {
+                    Operand::Immediate(target) => {
+                        if let Some(target) = target.to_address() {
+                            successors.push(Edge::Jump(target));
+                        }
+                    }
+                    _ => indirect = true,
+                }
+            }
+            if jumps != Some(true) {
+                successors.push(Edge::Next(next));
+            }
+
+            (successors, indirect)
+        }
+        _ => (vec![Edge::Next(next)], false),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::parse;
+
+    // Counts [20] down from 3, outputs it, then jumps through the address stored at [19].
+    const LOOP: &str = "1101,3,0,20,1001,20,-1,20,1005,20,4,4,20,106,0,19,99,0,0,16,0";
+
+    #[test]
+    fn test_blocks() {
+        let cfg: Cfg<DefaultWord> = Cfg::build(&parse(LOOP));
+
+        assert_eq!(
+            cfg.blocks.keys().copied().collect::<Vec<_>>(),
+            vec![0, 4, 11]
+        );
+        assert_eq!(cfg.block(0).unwrap().successors, vec![Edge::Next(4)]);
+        assert_eq!(
+            cfg.block(4).unwrap().successors,
+            vec![Edge::Jump(4), Edge::Next(11)]
+        );
+        assert_eq!(cfg.block(4).unwrap().end(), 11);
+
+        let exit = cfg.block(11).unwrap();
+        assert_eq!(exit.instructions.len(), 2);
+        assert!(exit.successors.is_empty());
+        assert!(exit.indirect);
+    }
+
+    #[test]
+    fn test_constant_conditions() {
+        let cfg: Cfg<DefaultWord> = Cfg::build(&parse("1105,1,4,1,1106,1,9,99,99,104,7,99"));
+
+        assert_eq!(cfg.block(0).unwrap().successors, vec![Edge::Jump(4)]);
+        assert_eq!(cfg.block(4).unwrap().successors, vec![Edge::Next(7)]);
+        assert_eq!(cfg.blocks.len(), 3);
+    }
+
+    #[test]
+    fn test_dot() {
+        let cfg: Cfg<DefaultWord> = Cfg::build(&parse(LOOP));
+        let dot = cfg.to_dot();
+
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(
+            dot.contains("    b4 [label=\"0004: add [20], #-1, [20]\\l0008: jnz [20], #4\\l\"];\n")
+        );
+        assert!(dot.contains("    b4 -> b4 [style=bold];\n"));
+        assert!(dot.contains("    b4 -> b11;\n"));
+        assert!(dot.contains("    b11 -> indirect [style=dashed];\n"));
+        assert!(dot.ends_with("    indirect [label=\"?\", shape=circle];\n}\n"));
+    }
+}
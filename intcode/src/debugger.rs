@@ -0,0 +1,472 @@
+use crate::disasm;
+use crate::memory::{Memory, PagedMemory};
+use crate::vm::{State, Vm};
+use crate::watch::Access;
+use crate::word::{DefaultWord, Word};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+step [n]           execute n instructions (default 1)
+continue           run until a breakpoint, input is needed or the program halts
+break [addr]       set a breakpoint, or list breakpoints
+delete <addr>      remove a breakpoint
+watch [addr]       stop after writes to addr, or list watchpoints
+rwatch <addr>      stop after reads from addr
+awatch <addr>      stop after reads from or writes to addr
+unwatch <addr>     remove a watchpoint
+selfmod            toggle stopping after writes into executed code
+regs               show pointer, relative base and pending input
+mem <addr> [n]     show n memory cells (default 8, at most 1024)
+list [addr] [n]    disassemble n instructions (default 5 from the pointer, at most 1024)
+poke <addr> <val>  write a value into memory
+input <val>...     queue input values
+quit               exit the debugger";
+
+/// The most cells `mem` shows or instructions `list` disassembles at once.
+const MAX_COUNT: usize = 1024;
+
+pub struct Debugger<W: Word = DefaultWord, M: Memory<W> = PagedMemory<W>> {
+    vm: Vm<W, M>,
+    breakpoints: BTreeSet<usize>,
+}
+
+impl<W: Word, M: Memory<W>> Debugger<W, M> {
+    pub fn new(vm: Vm<W, M>) -> Self {
+        Debugger {
+            vm,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn vm(&self) -> &Vm<W, M> {
+        &self.vm
+    }
+
+    pub fn run<R: BufRead, O: Write>(&mut self, input: R, mut output: O) -> io::Result<()> {
+        write!(output, "{}\n> ", self.location())?;
+        output.flush()?;
+
+        for line in input.lines() {
+            match self.command(&line?) {
+                Some(response) => {
+                    if !response.is_empty() {
+                        writeln!(output, "{}", response)?;
+                    }
+                    write!(output, "> ")?;
+                    output.flush()?;
+                }
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs a single debugger command, returning the text to show or `None` to quit.
+    pub fn command(&mut self, line: &str) -> Option<String> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Some(String::new()),
+        };
+        let args: Result<Vec<i128>, _> = words.map(str::parse).collect();
+        let args = match args {
+            Ok(args) => args,
+            Err(e) => return Some(format!("invalid argument: {}", e)),
+        };
+
+        if let ("q", []) | ("quit", []) = (command, args.as_slice()) {
+            return None;
+        }
+
+        Some(self.dispatch(line, command, &args).unwrap_or_else(|e| e))
+    }
+
+    fn dispatch(&mut self, line: &str, command: &str, args: &[i128]) -> Result<String, String> {
+        let response = match (command, args) {
+            ("h", []) | ("help", []) => HELP.to_string(),
+            ("s", []) | ("step", []) => self.step(1),
+            ("s", [n]) | ("step", [n]) if *n > 0 => self.step(index(*n)?),
+            ("c", []) | ("continue", []) => self.resume(),
+            ("b", []) | ("break", []) => self
+                .breakpoints
+                .iter()
+                .map(|address| format!("{:04}", address))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            ("b", [address]) | ("break", [address]) => {
+                self.breakpoints.insert(index(*address)?);
+                format!("breakpoint at {:04}", address)
+            }
+            ("d", [address]) | ("delete", [address]) => {
+                if self.breakpoints.remove(&index(*address)?) {
+                    format!("deleted breakpoint at {:04}", address)
+                } else {
+                    format!("no breakpoint at {:04}", address)
+                }
+            }
+            ("w", []) | ("watch", []) => self
+                .vm
+                .watchpoints()
+                .iter()
+                .map(|(address, access)| format!("{:04} {}", address, access))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            ("w", [address]) | ("watch", [address]) => {
+                self.vm.watch(index(*address)?, Access::Write);
+                format!("watching writes to {:04}", address)
+            }
+            ("rwatch", [address]) => {
+                self.vm.watch(index(*address)?, Access::Read);
+                format!("watching reads from {:04}", address)
+            }
+            ("awatch", [address]) => {
+                self.vm.watch(index(*address)?, Access::ReadWrite);
+                format!("watching reads from and writes to {:04}", address)
+            }
+            ("unwatch", [address]) => {
+                if self.vm.unwatch(index(*address)?) {
+                    format!("deleted watchpoint at {:04}", address)
+                } else {
+                    format!("no watchpoint at {:04}", address)
+                }
+            }
+            ("selfmod", []) => {
+                let enabled = !self.vm.detects_self_modification();
+                self.vm.detect_self_modification(enabled);
+                format!(
+                    "self-modification detection {}",
+                    if enabled { "on" } else { "off" }
+                )
+            }
+            ("r", []) | ("regs", []) => format!(
+                "pointer: {}\nrelative base: {}\ninput: {:?}",
+                self.vm.pointer(),
+                self.vm.relative_base(),
+                self.vm.input()
+            ),
+            ("x", [address]) | ("mem", [address]) => self.memory(index(*address)?, 8)?,
+            ("x", [address, n]) | ("mem", [address, n]) => {
+                self.memory(index(*address)?, count(*n)?)?
+            }
+            ("l", []) | ("list", []) => self.list(self.vm.pointer(), 5),
+            ("l", [address]) | ("list", [address]) => self.list(index(*address)?, 5),
+            ("l", [address, n]) | ("list", [address, n]) => self.list(index(*address)?, count(*n)?),
+            ("poke", [address, value]) => match W::try_from(*value) {
+                Ok(value) => {
+                    self.vm.set(index(*address)?, value.clone());
+                    format!("{:04}: {}", address, value)
+                }
+                Err(_) => format!("value out of range: {}", value),
+            },
+            ("i", values) | ("input", values) if !values.is_empty() => {
+                match values
+                    .iter()
+                    .map(|&value| W::try_from(value))
+                    .collect::<Result<Vec<W>, _>>()
+                {
+                    Ok(values) => {
+                        for value in values {
+                            self.vm.add_input(value);
+                        }
+                        format!("input: {:?}", self.vm.input())
+                    }
+                    Err(_) => "value out of range".to_string(),
+                }
+            }
+            _ => format!("unrecognised command: {} (try help)", line.trim()),
+        };
+
+        Ok(response)
+    }
+
+    fn step(&mut self, count: usize) -> String {
+        let mut lines = Vec::new();
+
+        for _ in 0..count {
+            let stopped = self.single(&mut lines);
+            if stopped {
+                break;
+            }
+        }
+
+        lines.push(self.location());
+        lines.join("\n")
+    }
+
+    fn resume(&mut self) -> String {
+        let mut lines = Vec::new();
+
+        loop {
+            if self.single(&mut lines) {
+                break;
+            }
+            if self.breakpoints.contains(&self.vm.pointer()) {
+                lines.push(format!("breakpoint at {:04}", self.vm.pointer()));
+                break;
+            }
+        }
+
+        lines.push(self.location());
+        lines.join("\n")
+    }
+
+    /// Executes one instruction, describing anything notable. Returns true if execution can't
+    /// carry on without intervention.
+    fn single(&mut self, lines: &mut Vec<String>) -> bool {
+        let result = self.vm.step();
+
+        let hits = self.vm.take_hits();
+        for hit in &hits {
+            lines.push(format!("watchpoint: {}", hit));
+        }
+
+        let stopped = match result {
+            Ok(None) => false,
+            Ok(Some(State::Output(value))) => {
+                lines.push(format!("output: {}", value));
+                false
+            }
+            Ok(Some(State::AwaitingInput)) => {
+                lines.push("awaiting input".to_string());
+                true
+            }
+            Ok(Some(State::Halted)) => {
+                lines.push("halted".to_string());
+                true
+            }
+            Ok(Some(State::BudgetExhausted)) => {
+                lines.push("budget exhausted".to_string());
+                true
+            }
+            Err(e) => {
+                lines.push(format!("error: {}", e));
+                true
+            }
+        };
+
+        stopped || !hits.is_empty()
+    }
+
+    fn location(&self) -> String {
+        self.list(self.vm.pointer(), 1)
+    }
+
+    fn memory(&self, address: usize, count: usize) -> Result<String, String> {
+        let end = address
+            .checked_add(count)
+            .ok_or_else(|| format!("out of range: {} + {}", address, count))?;
+
+        Ok((address..end)
+            .step_by(8)
+            .map(|row| {
+                let values = (row..end.min(row + 8))
+                    .map(|address| self.vm.get(address).to_string())
+                    .collect::<Vec<String>>();
+                format!("{:04}: {}", row, values.join(", "))
+            })
+            .collect::<Vec<String>>()
+            .join("\n"))
+    }
+
+    /// Disassembles `count` instructions from `address`, reading only the cells they use.
+    fn list(&self, mut address: usize, count: usize) -> String {
+        let mut lines = Vec::new();
+
+        for _ in 0..count {
+            let window: Vec<W> = (0..4)
+                .map_while(|offset| address.checked_add(offset))
+                .map(|address| self.vm.get(address))
+                .collect();
+
+            let line = match disasm::decode(&window, 0) {
+                Some(disasm::Line::Instruction {
+                    mnemonic, operands, ..
+                }) => disasm::Line::Instruction {
+                    address,
+                    mnemonic,
+                    operands,
+                },
+                _ => disasm::Line::Data {
+                    address,
+                    value: window[0].clone(),
+                },
+            };
+            let length = match &line {
+                disasm::Line::Instruction { operands, .. } => 1 + operands.len(),
+                disasm::Line::Data { .. } => 1,
+            };
+            lines.push(line.to_string());
+
+            address = match address.checked_add(length) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// Converts an address or count argument.
+fn index(value: i128) -> Result<usize, String> {
+    usize::try_from(value).map_err(|_| format!("out of range: {}", value))
+}
+
+/// Converts the number of cells or instructions to show.
+fn count(value: i128) -> Result<usize, String> {
+    match index(value)? {
+        count if count <= MAX_COUNT => Ok(count),
+        _ => Err(format!("count must be at most {}: {}", MAX_COUNT, value)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::word::w;
+
+    #[test]
+    fn test_step() {
+        let mut debugger = Debugger::new(Vm::from("1,9,10,3,2,3,11,0,99,30,40,50"));
+
+        assert_eq!(
+            debugger.command("step").unwrap(),
+            "0004: mul [3], [11], [0]"
+        );
+        assert_eq!(debugger.command("s 5").unwrap(), "halted\n0008: hlt");
+        assert_eq!(debugger.vm().get(0), w(3500));
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut debugger = Debugger::new(Vm::from("104,1,104,2,104,3,99"));
+
+        debugger.command("break 4");
+        assert_eq!(
+            debugger.command("continue").unwrap(),
+            "output: 1\noutput: 2\nbreakpoint at 0004\n0004: out #3"
+        );
+        assert_eq!(
+            debugger.command("c").unwrap(),
+            "output: 3\nhalted\n0006: hlt"
+        );
+    }
+
+    #[test]
+    fn test_input_and_poke() {
+        let mut debugger = Debugger::new(Vm::from("3,9,1,9,10,9,4,9,99"));
+
+        assert_eq!(
+            debugger.command("c").unwrap(),
+            "awaiting input\n0000: in [9]"
+        );
+        assert_eq!(debugger.command("input 5").unwrap(), "input: [5]");
+        debugger.command("poke 10 37");
+        assert_eq!(
+            debugger.command("regs").unwrap(),
+            "pointer: 0\nrelative base: 0\ninput: [5]"
+        );
+        assert_eq!(
+            debugger.command("c").unwrap(),
+            "output: 42\nhalted\n0008: hlt"
+        );
+        assert_eq!(debugger.command("mem 8 3").unwrap(), "0008: 99, 42, 37");
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut debugger = Debugger::new(Vm::from("1,9,10,3,2,3,11,0,99,30,40,50"));
+
+        assert_eq!(
+            debugger.command("rwatch 11").unwrap(),
+            "watching reads from 0011"
+        );
+        debugger.command("selfmod");
+        assert_eq!(
+            debugger.command("c").unwrap(),
+            "watchpoint: 0000 modified code at 0003 (now 70)\n0004: mul [3], [11], [0]"
+        );
+        assert_eq!(
+            debugger.command("c").unwrap(),
+            "watchpoint: 0004 read 50 from 0011\n\
+             watchpoint: 0004 modified code at 0000 (now 3500)\n0008: hlt"
+        );
+        assert_eq!(debugger.command("watch").unwrap(), "0011 reads");
+        debugger.command("awatch 3");
+        assert_eq!(
+            debugger.command("watch").unwrap(),
+            "0003 reads and writes\n0011 reads"
+        );
+        assert_eq!(
+            debugger.command("selfmod").unwrap(),
+            "self-modification detection off"
+        );
+    }
+
+    #[test]
+    fn test_quit() {
+        let mut debugger = Debugger::new(Vm::from("99"));
+
+        assert_eq!(debugger.command("quit"), None);
+        assert!(debugger
+            .command("bogus")
+            .unwrap()
+            .starts_with("unrecognised"));
+    }
+
+    #[test]
+    fn test_counts() {
+        let mut debugger = Debugger::new(Vm::from("1101,1,2,5,99"));
+
+        assert_eq!(debugger.command("mem 0 1024").unwrap().lines().count(), 128);
+        assert_eq!(
+            debugger.command("list 0 1024").unwrap().lines().count(),
+            1024
+        );
+        for command in [
+            "mem 0 18446744073709551615",
+            "mem 0 100000000000",
+            "list 0 1025",
+        ] {
+            assert!(debugger
+                .command(command)
+                .unwrap()
+                .starts_with("count must be at most 1024: "));
+        }
+    }
+
+    #[test]
+    fn test_far_addresses() {
+        let mut debugger = Debugger::new(Vm::from("1101,1,2,5,99"));
+
+        assert_eq!(
+            debugger.command("poke 1000000000000 99").unwrap(),
+            "1000000000000: 99"
+        );
+        assert_eq!(
+            debugger.command("list 1000000000000 2").unwrap(),
+            "1000000000000: hlt\n1000000000001: data 0"
+        );
+        assert_eq!(debugger.command("list").unwrap().lines().count(), 5);
+        assert_eq!(
+            debugger.command("mem 1000000000000 1").unwrap(),
+            "1000000000000: 99"
+        );
+        assert_eq!(debugger.command("mem -1").unwrap(), "out of range: -1");
+        assert_eq!(
+            debugger.command("break 1e3").unwrap(),
+            "invalid argument: invalid digit found in string"
+        );
+        assert_eq!(
+            debugger.command("mem 18446744073709551615 2").unwrap(),
+            "out of range: 18446744073709551615 + 2"
+        );
+        assert_eq!(
+            debugger.command("poke 99999999999999999999999 1").unwrap(),
+            "out of range: 99999999999999999999999"
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use crate::budget::Budget;
+use crate::memory::Memory;
+use crate::vm::{State, Vm};
+use crate::word::{DefaultWord, Word};
+use crate::VmError;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, StdinLock, Stdout, Write};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum DeviceError<W = DefaultWord> {
+    /// The VM being run failed.
+    Vm(VmError<W>),
+    /// A VM used as an input failed while producing its next output.
+    Input(VmError<W>),
+    Io(io::Error),
+    InvalidNumber(String),
+}
+
+impl<W: Word> fmt::Display for DeviceError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceError::Vm(e) => write!(f, "{}", e),
+            DeviceError::Input(e) => write!(f, "input vm failed: {}", e),
+            DeviceError::Io(e) => write!(f, "device i/o failed: {}", e),
+            DeviceError::InvalidNumber(line) => write!(f, "invalid number {}", line),
+        }
+    }
+}
+
+impl<W: Word> Error for DeviceError<W> {}
+
+impl<W> From<VmError<W>> for DeviceError<W> {
+    fn from(e: VmError<W>) -> Self {
+        DeviceError::Vm(e)
+    }
+}
+
+impl<W> From<io::Error> for DeviceError<W> {
+    fn from(e: io::Error) -> Self {
+        DeviceError::Io(e)
+    }
+}
+
+/// Somewhere a VM can pull input from. `None` means nothing is available yet.
+pub trait Input<W> {
+    fn read(&mut self) -> Result<Option<W>, DeviceError<W>>;
+}
+
+/// Somewhere a VM can send its output.
+pub trait Output<W> {
+    fn write(&mut self, value: W) -> Result<(), DeviceError<W>>;
+}
+
+impl<W> Input<W> for VecDeque<W> {
+    fn read(&mut self) -> Result<Option<W>, DeviceError<W>> {
+        Ok(self.pop_front())
+    }
+}
+
+impl<W> Output<W> for VecDeque<W> {
+    fn write(&mut self, value: W) -> Result<(), DeviceError<W>> {
+        self.push_back(value);
+        Ok(())
+    }
+}
+
+impl<W> Output<W> for Vec<W> {
+    fn write(&mut self, value: W) -> Result<(), DeviceError<W>> {
+        self.push(value);
+        Ok(())
+    }
+}
+
+/// A single value, read once.
+impl<W> Input<W> for Option<W> {
+    fn read(&mut self) -> Result<Option<W>, DeviceError<W>> {
+        Ok(self.take())
+    }
+}
+
+impl<W, F: FnMut() -> Option<W>> Input<W> for F {
+    fn read(&mut self) -> Result<Option<W>, DeviceError<W>> {
+        Ok(self())
+    }
+}
+
+impl<W, F: FnMut(W)> Output<W> for F {
+    fn write(&mut self, value: W) -> Result<(), DeviceError<W>> {
+        self(value);
+        Ok(())
+    }
+}
+
+/// Reading from another VM runs it to its next output.
+impl<W: Word, M: Memory<W>> Input<W> for Vm<W, M> {
+    fn read(&mut self) -> Result<Option<W>, DeviceError<W>> {
+        match self.execute() {
+            Ok(State::Output(value)) => Ok(Some(value)),
+            Ok(_) => Ok(None),
+            Err(e) => Err(DeviceError::Input(e)),
+        }
+    }
+}
+
+/// Writing to another VM queues the value as its input.
+impl<W: Word, M: Memory<W>> Output<W> for Vm<W, M> {
+    fn write(&mut self, value: W) -> Result<(), DeviceError<W>> {
+        self.add_input(value);
+        Ok(())
+    }
+}
+
+/// Reads one number per line, skipping blank lines.
+pub struct TextInput<R: BufRead> {
+    reader: R,
+}
+
+impl<R: BufRead> TextInput<R> {
+    pub fn new(reader: R) -> Self {
+        TextInput { reader }
+    }
+}
+
+impl TextInput<StdinLock<'static>> {
+    pub fn stdin() -> Self {
+        TextInput::new(io::stdin().lock())
+    }
+}
+
+impl TextInput<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(TextInput::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<W: Word, R: BufRead> Input<W> for TextInput<R> {
+    fn read(&mut self) -> Result<Option<W>, DeviceError<W>> {
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+
+            let line = line.trim();
+            if !line.is_empty() {
+                return match line.parse() {
+                    Ok(value) => Ok(Some(value)),
+                    Err(_) => Err(DeviceError::InvalidNumber(line.to_string())),
+                };
+            }
+        }
+    }
+}
+
+/// Writes one number per line.
+pub struct TextOutput<T: Write> {
+    writer: T,
+}
+
+impl<T: Write> TextOutput<T> {
+    pub fn new(writer: T) -> Self {
+        TextOutput { writer }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.writer
+    }
+}
+
+impl TextOutput<Stdout> {
+    pub fn stdout() -> Self {
+        TextOutput::new(io::stdout())
+    }
+}
+
+impl TextOutput<File> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(TextOutput::new(File::create(path)?))
+    }
+}
+
+impl<W: Word, T: Write> Output<W> for TextOutput<T> {
+    fn write(&mut self, value: W) -> Result<(), DeviceError<W>> {
+        Ok(writeln!(self.writer, "{}", value)?)
+    }
+}
+
+impl<W: Word, M: Memory<W>> Vm<W, M> {
+    /// Runs until the program halts or needs input that `input` can't supply yet, sending
+    /// every output to `output`.
+    pub fn run<I: Input<W>, O: Output<W>>(
+        &mut self,
+        input: &mut I,
+        output: &mut O,
+    ) -> Result<State<W>, DeviceError<W>> {
+        self.run_within(input, output, Budget::new())
+    }
+
+    /// Like `run`, but stops with `State::BudgetExhausted` when `budget` runs out.
+    pub fn run_within<I: Input<W>, O: Output<W>>(
+        &mut self,
+        input: &mut I,
+        output: &mut O,
+        budget: Budget,
+    ) -> Result<State<W>, DeviceError<W>> {
+        let mut executed = 0;
+
+        loop {
+            if budget.exhausted(executed) {
+                return Ok(State::BudgetExhausted);
+            }
+
+            match self.step()? {
+                None => {}
+                Some(State::Output(value)) => output.write(value)?,
+                Some(State::AwaitingInput) => match input.read()? {
+                    Some(value) => {
+                        self.add_input(value);
+                        continue;
+                    }
+                    None => return Ok(State::AwaitingInput),
+                },
+                Some(state) => return Ok(state),
+            }
+            executed += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::word::{w, DefaultWord};
+
+    const DOUBLER: &str = "3,11,102,2,11,11,4,11,1105,1,0";
+
+    #[test]
+    fn test_buffers() {
+        let mut vm = Vm::from(DOUBLER);
+        let mut input: VecDeque<DefaultWord> = (1..=3).map(w).collect();
+        let mut output = Vec::new();
+
+        assert_eq!(
+            vm.run(&mut input, &mut output).unwrap(),
+            State::AwaitingInput
+        );
+        assert_eq!(output, vec![w(2), w(4), w(6)]);
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn test_closures() {
+        let mut vm = Vm::from(DOUBLER);
+        let mut next = 0;
+        let mut total = w(0);
+
+        let state = vm.run(
+            &mut || {
+                next += 1;
+                if next <= 4 {
+                    Some(w(next))
+                } else {
+                    None
+                }
+            },
+            &mut |value| total += value,
+        );
+
+        assert_eq!(state.unwrap(), State::AwaitingInput);
+        assert_eq!(total, w(20));
+    }
+
+    #[test]
+    fn test_text() {
+        let mut vm = Vm::from(DOUBLER);
+        let mut input = TextInput::new("5\n\n-7\n".as_bytes());
+        let mut output = TextOutput::new(Vec::new());
+
+        vm.run(&mut input, &mut output).unwrap();
+        assert_eq!(output.into_inner(), b"10\n-14\n");
+    }
+
+    #[test]
+    fn test_chained_vms() {
+        let mut first = Vm::from(DOUBLER);
+        let mut second = Vm::from(DOUBLER);
+        first.add_input(w(3));
+
+        let mut output = Vec::new();
+        assert_eq!(
+            second.run(&mut first, &mut output).unwrap(),
+            State::AwaitingInput
+        );
+        assert_eq!(output, vec![w(12)]);
+
+        let mut third = Vm::from(DOUBLER);
+        assert_eq!(
+            second.run(&mut Some(w(1)), &mut third).unwrap(),
+            State::AwaitingInput
+        );
+        assert_eq!(third.execute(), Ok(State::Output(w(4))));
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut vm = Vm::from("1105,1,0");
+
+        assert_eq!(
+            vm.run_within(&mut None, &mut Vec::new(), Budget::new().steps(10))
+                .unwrap(),
+            State::BudgetExhausted
+        );
+        assert_eq!(vm.steps(), 10);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut vm = Vm::from(DOUBLER);
+        match vm.run(&mut TextInput::new("5\nfive\n".as_bytes()), &mut Vec::new()) {
+            Err(DeviceError::InvalidNumber(line)) => assert_eq!(line, "five"),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let mut broken = Vm::from("42");
+        match Vm::from(DOUBLER).run(&mut broken, &mut Vec::new()) {
+            Err(DeviceError::Input(VmError::UnknownOpcode { pointer: 0, .. })) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let mut full = [0u8; 2];
+        let mut output = TextOutput::new(&mut full[..]);
+        match Vm::from(DOUBLER).run(&mut Some(w(50)), &mut output) {
+            Err(DeviceError::Io(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::vm::{Opcode, ParameterMode};
+use crate::word::{DefaultWord, Word};
+use std::collections::BTreeSet;
+use std::fmt;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Operand<W = DefaultWord> {
+    Position(W),
+    Immediate(W),
+    Relative(W),
+}
+
+impl<W> Operand<W> {
+    fn new(mode: ParameterMode, value: W) -> Self {
+        match mode {
+            ParameterMode::Position => Operand::Position(value),
+            ParameterMode::Immediate => Operand::Immediate(value),
+            ParameterMode::Relative => Operand::Relative(value),
+        }
+    }
+}
+
+impl<W: Word> fmt::Display for Operand<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Position(address) => write!(f, "[{}]", address),
+            Operand::Immediate(value) => write!(f, "#{}", value),
+            Operand::Relative(offset) if offset.is_negative() => write!(f, "rb{}", offset),
+            Operand::Relative(offset) => write!(f, "rb+{}", offset),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Line<W = DefaultWord> {
+    Instruction {
+        address: usize,
+        mnemonic: &'static str,
+        operands: Vec<Operand<W>>,
+    },
+    Data {
+        address: usize,
+        value: W,
+    },
+}
+
+impl<W> Line<W> {
+    pub fn address(&self) -> usize {
+        match *self {
+            Line::Instruction { address, .. } | Line::Data { address, .. } => address,
+        }
+    }
+}
+
+impl<W: Word> fmt::Display for Line<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Instruction {
+                address,
+                mnemonic,
+                operands,
+            } => {
+                write!(f, "{:04}: {}", address, mnemonic)?;
+                for (i, operand) in operands.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { ", " }, operand)?;
+                }
+                Ok(())
+            }
+            Line::Data { address, value } => write!(f, "{:04}: data {}", address, value),
+        }
+    }
+}
+
+pub fn mnemonic(operation: usize) -> Option<&'static str> {
+    match operation {
+        1 => Some("add"),
+        2 => Some("mul"),
+        3 => Some("in"),
+        4 => Some("out"),
+        5 => Some("jnz"),
+        6 => Some("jz"),
+        7 => Some("lt"),
+        8 => Some("eq"),
+        9 => Some("arb"),
+        99 => Some("hlt"),
+        _ => None,
+    }
+}
+
+pub fn operation(mnemonic: &str) -> Option<usize> {
+    (1..=9)
+        .chain(Some(99))
+        .find(|&operation| self::mnemonic(operation) == Some(mnemonic))
+}
+
+/// Decodes the instruction at `address`, returning `None` if the cell does not hold a valid
+/// opcode or the operands run past the end of the program.
+pub fn decode<W: Word>(program: &[W], address: usize) -> Option<Line<W>> {
+    let opcode = Opcode::decode(program.get(address)?, address).ok()?;
+    let mnemonic = mnemonic(opcode.operation)?;
+    let count = opcode.parameter_count()?;
+
+    let operands = program
+        .get(address + 1..address + 1 + count)?
+        .iter()
+        .enumerate()
+        .map(|(i, value)| Operand::new(opcode.get_mode(i), value.clone()))
+        .collect();
+
+    Some(Line::Instruction {
+        address,
+        mnemonic,
+        operands,
+    })
+}
+
+/// Walks every path reachable from address 0, following jumps whose targets are immediate.
+/// Cells that no path decodes as an instruction are listed as data.
+pub fn disassemble<W: Word>(program: &[W]) -> Vec<Line<W>> {
+    let mut code = BTreeSet::new();
+    let mut pending = vec![0];
+    let mut visited = BTreeSet::new();
+
+    while let Some(address) = pending.pop() {
+        if !visited.insert(address) {
+            continue;
+        }
+
+        let (mnemonic, operands) = match decode(program, address) {
+            Some(Line::Instruction {
+                mnemonic, operands, ..
+            }) => (mnemonic, operands),
+            _ => continue,
+        };
+
+        code.insert(address);
+        let next = address + 1 + operands.len();
+
+        match (mnemonic, operands.as_slice()) {
+            ("hlt", _) => {}
+            ("jnz", [condition, target]) | ("jz", [condition, target]) => {
+                if let Operand::Immediate(target) = target {
+                    if let Some(target) = target.to_address() {
+                        pending.push(target);
+                    }
+                }
+
+                let always = match condition {
+                    Operand::Immediate(value) => value.is_zero() != (mnemonic == "jnz"),
+                    _ => false,
+                };
+                if !always {
+                    pending.push(next);
+                }
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < program.len() {
+        match decode(program, address) {
+            Some(line) if code.contains(&address) => {
+                if let Line::Instruction { ref operands, .. } = line {
+                    address += 1 + operands.len();
+                }
+                lines.push(line);
+            }
+            _ => {
+                lines.push(Line::Data {
+                    address,
+                    value: program[address].clone(),
+                });
+                address += 1;
+            }
+        }
+    }
+
+    lines
+}
+
+pub fn listing<W: Word>(program: &[W]) -> String {
+    disassemble(program)
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::parse;
+    use crate::word::DefaultWord;
+
+    #[test]
+    fn test_operands() {
+        let program: Vec<DefaultWord> = parse("1001,4,3,4,22201,-1,2,3,99");
+
+        assert_eq!(
+            listing(&program),
+            "0000: add [4], #3, [4]\n0004: add rb-1, rb+2, rb+3\n0008: hlt\n"
+        );
+    }
+
+    #[test]
+    fn test_data_after_halt() {
+        let program: Vec<DefaultWord> = parse("1,9,10,3,2,3,11,0,99,30,40,50");
+
+        assert_eq!(
+            listing(&program),
+            "0000: add [9], [10], [3]\n\
+             0004: mul [3], [11], [0]\n\
+             0008: hlt\n\
+             0009: data 30\n\
+             0010: data 40\n\
+             0011: data 50\n"
+        );
+    }
+
+    #[test]
+    fn test_follows_immediate_jumps() {
+        let program: Vec<DefaultWord> = parse("1105,1,4,1,104,7,99");
+
+        assert_eq!(
+            listing(&program),
+            "0000: jnz #1, #4\n0003: data 1\n0004: out #7\n0006: hlt\n"
+        );
+    }
+
+    #[test]
+    fn test_unknown_opcode_is_data() {
+        let program: Vec<DefaultWord> = parse("42,99");
+
+        assert_eq!(listing(&program), "0000: data 42\n0001: data 99\n");
+    }
+}
@@ -0,0 +1,93 @@
+use crate::word::{DefaultWord, Word};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VmError<W = DefaultWord> {
+    InvalidMode {
+        pointer: usize,
+        opcode: W,
+        mode: i128,
+    },
+    UnknownOpcode {
+        pointer: usize,
+        opcode: W,
+    },
+    NegativeAddress {
+        pointer: usize,
+        address: W,
+    },
+    AddressOverflow {
+        pointer: usize,
+        address: W,
+    },
+    ImmediateWrite {
+        pointer: usize,
+        opcode: W,
+    },
+    Overflow {
+        pointer: usize,
+        opcode: W,
+        lhs: W,
+        rhs: W,
+    },
+}
+
+impl<W> VmError<W> {
+    pub fn pointer(&self) -> usize {
+        match *self {
+            VmError::InvalidMode { pointer, .. }
+            | VmError::UnknownOpcode { pointer, .. }
+            | VmError::NegativeAddress { pointer, .. }
+            | VmError::AddressOverflow { pointer, .. }
+            | VmError::ImmediateWrite { pointer, .. }
+            | VmError::Overflow { pointer, .. } => pointer,
+        }
+    }
+}
+
+impl<W: Word> fmt::Display for VmError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::InvalidMode {
+                pointer,
+                opcode,
+                mode,
+            } => write!(
+                f,
+                "invalid parameter mode {} in opcode {} at {}",
+                mode, opcode, pointer
+            ),
+            VmError::UnknownOpcode { pointer, opcode } => {
+                write!(f, "unknown opcode {} at {}", opcode, pointer)
+            }
+            VmError::NegativeAddress { pointer, address } => write!(
+                f,
+                "negative address {} for instruction at {}",
+                address, pointer
+            ),
+            VmError::AddressOverflow { pointer, address } => write!(
+                f,
+                "address {} out of range for instruction at {}",
+                address, pointer
+            ),
+            VmError::ImmediateWrite { pointer, opcode } => write!(
+                f,
+                "write in immediate mode by opcode {} at {}",
+                opcode, pointer
+            ),
+            VmError::Overflow {
+                pointer,
+                opcode,
+                lhs,
+                rhs,
+            } => write!(
+                f,
+                "overflow in opcode {} at {} with operands {} and {}",
+                opcode, pointer, lhs, rhs
+            ),
+        }
+    }
+}
+
+impl<W: Word> Error for VmError<W> {}
@@ -0,0 +1,26 @@
+pub use crate::budget::Budget;
+pub use crate::error::VmError;
+pub use crate::memory::{Memory, PagedMemory};
+pub use crate::vm::{parse, Overflow, ParameterMode, State, Vm};
+pub use crate::word::{DefaultWord, Word};
+
+pub mod asm;
+mod budget;
+pub mod cfg;
+pub mod debugger;
+pub mod device;
+pub mod disasm;
+mod error;
+mod memory;
+pub mod network;
+pub mod optimize;
+pub mod profile;
+pub mod replay;
+pub mod search;
+pub mod snapshot;
+pub mod symbolic;
+pub mod trace;
+pub mod transpile;
+mod vm;
+pub mod watch;
+mod word;
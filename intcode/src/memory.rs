@@ -0,0 +1,250 @@
+use crate::word::{DefaultWord, Word};
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+/// Storage for a VM's address space. Cells that were never written read as zero, but `fetch`
+/// reports them as absent so the VM can treat running off the end of the program as a halt.
+pub trait Memory<W: Word = DefaultWord>: Clone + Debug {
+    fn from_program(program: Vec<W>) -> Self;
+
+    fn get(&self, address: usize) -> W;
+
+    fn set(&mut self, address: usize, value: W);
+
+    fn fetch(&self, address: usize) -> Option<W>;
+
+    /// Every written cell, in address order.
+    fn cells(&self) -> Vec<(usize, W)>;
+}
+
+impl<W: Word> Memory<W> for HashMap<usize, W> {
+    fn from_program(program: Vec<W>) -> Self {
+        program.into_iter().enumerate().collect()
+    }
+
+    fn get(&self, address: usize) -> W {
+        HashMap::get(self, &address)
+            .cloned()
+            .unwrap_or_else(|| W::from(0))
+    }
+
+    fn set(&mut self, address: usize, value: W) {
+        self.insert(address, value);
+    }
+
+    fn fetch(&self, address: usize) -> Option<W> {
+        HashMap::get(self, &address).cloned()
+    }
+
+    fn cells(&self) -> Vec<(usize, W)> {
+        let mut cells: Vec<(usize, W)> = self.iter().map(|(&k, v)| (k, v.clone())).collect();
+        cells.sort_unstable();
+        cells
+    }
+}
+
+const PAGE_SIZE: usize = 1024;
+const DENSE_LIMIT: usize = 1 << 20;
+
+#[derive(Clone, Debug)]
+struct Page<W> {
+    cells: Vec<W>,
+    written: Vec<u64>,
+}
+
+impl<W: Word> Page<W> {
+    fn new() -> Self {
+        Page {
+            cells: vec![W::from(0); PAGE_SIZE],
+            written: vec![0; PAGE_SIZE / 64],
+        }
+    }
+}
+
+/// A `Vec` covering the program image and everything up to `DENSE_LIMIT`, with far-away
+/// addresses kept in sparse pages.
+#[derive(Clone, Debug, Default)]
+pub struct PagedMemory<W = DefaultWord> {
+    dense: Vec<W>,
+    written: Vec<u64>,
+    pages: HashMap<usize, Page<W>>,
+}
+
+impl<W> PagedMemory<W> {
+    /// Whether `address` is kept in `dense`, which covers a program image longer than
+    /// `DENSE_LIMIT` too.
+    #[inline]
+    fn is_dense(&self, address: usize) -> bool {
+        address < self.dense.len().max(DENSE_LIMIT)
+    }
+
+    fn is_written(written: &[u64], index: usize) -> bool {
+        written[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn mark_written(written: &mut [u64], index: usize) {
+        written[index / 64] |= 1 << (index % 64);
+    }
+}
+
+impl<W: Word> Memory<W> for PagedMemory<W> {
+    fn from_program(program: Vec<W>) -> Self {
+        let mut written = vec![0; program.len().div_ceil(64)];
+        for index in 0..program.len() {
+            Self::mark_written(&mut written, index);
+        }
+
+        PagedMemory {
+            dense: program,
+            written,
+            pages: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    fn get(&self, address: usize) -> W {
+        if self.is_dense(address) {
+            self.dense
+                .get(address)
+                .cloned()
+                .unwrap_or_else(|| W::from(0))
+        } else {
+            self.pages.get(&(address / PAGE_SIZE)).map_or_else(
+                || W::from(0),
+                |page| page.cells[address % PAGE_SIZE].clone(),
+            )
+        }
+    }
+
+    #[inline]
+    fn set(&mut self, address: usize, value: W) {
+        if self.is_dense(address) {
+            if address >= self.dense.len() {
+                let length = (address / PAGE_SIZE + 1) * PAGE_SIZE;
+                self.dense.resize(length, W::from(0));
+                self.written.resize(length / 64, 0);
+            }
+
+            self.dense[address] = value;
+            Self::mark_written(&mut self.written, address);
+        } else {
+            let page = self
+                .pages
+                .entry(address / PAGE_SIZE)
+                .or_insert_with(Page::new);
+
+            page.cells[address % PAGE_SIZE] = value;
+            Self::mark_written(&mut page.written, address % PAGE_SIZE);
+        }
+    }
+
+    #[inline]
+    fn fetch(&self, address: usize) -> Option<W> {
+        if address < self.dense.len() {
+            if Self::is_written(&self.written, address) {
+                Some(self.dense[address].clone())
+            } else {
+                None
+            }
+        } else {
+            let page = self.pages.get(&(address / PAGE_SIZE))?;
+            if Self::is_written(&page.written, address % PAGE_SIZE) {
+                Some(page.cells[address % PAGE_SIZE].clone())
+            } else {
+                None
+            }
+        }
+    }
+
+    fn cells(&self) -> Vec<(usize, W)> {
+        let mut cells: Vec<(usize, W)> = (0..self.dense.len())
+            .filter(|&address| Self::is_written(&self.written, address))
+            .map(|address| (address, self.dense[address].clone()))
+            .collect();
+
+        let mut pages: Vec<(&usize, &Page<W>)> = self.pages.iter().collect();
+        pages.sort_unstable_by_key(|&(&index, _)| index);
+        for (&index, page) in pages {
+            cells.extend(
+                (0..PAGE_SIZE)
+                    .filter(|&offset| Self::is_written(&page.written, offset))
+                    .map(|offset| (index * PAGE_SIZE + offset, page.cells[offset].clone())),
+            );
+        }
+
+        cells
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::word::w;
+
+    fn check<M: Memory>() {
+        let mut memory = M::from_program(vec![w(1), w(2), w(3)]);
+
+        assert_eq!(memory.fetch(2), Some(w(3)));
+        assert_eq!(memory.fetch(3), None);
+        assert_eq!(memory.get(3), w(0));
+
+        memory.set(3, w(4));
+        memory.set(5000, w(5));
+        memory.set(DENSE_LIMIT + 7, w(6));
+        memory.set(usize::MAX / 2, w(7));
+
+        assert_eq!(memory.fetch(3), Some(w(4)));
+        assert_eq!(memory.fetch(4), None);
+        assert_eq!(memory.get(5000), w(5));
+        assert_eq!(memory.fetch(4999), None);
+        assert_eq!(memory.get(DENSE_LIMIT + 7), w(6));
+        assert_eq!(memory.fetch(DENSE_LIMIT + 8), None);
+        assert_eq!(memory.get(usize::MAX / 2), w(7));
+        assert_eq!(
+            memory.cells(),
+            vec![
+                (0, w(1)),
+                (1, w(2)),
+                (2, w(3)),
+                (3, w(4)),
+                (5000, w(5)),
+                (DENSE_LIMIT + 7, w(6)),
+                (usize::MAX / 2, w(7))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hash_map() {
+        check::<HashMap<usize, DefaultWord>>();
+    }
+
+    #[test]
+    fn test_paged() {
+        check::<PagedMemory>();
+    }
+
+    #[test]
+    fn test_long_image() {
+        let mut memory = PagedMemory::from_program(vec![w(1); DENSE_LIMIT + 10]);
+        memory.set(DENSE_LIMIT + 5, w(11));
+        memory.set(DENSE_LIMIT + 20, w(12));
+
+        assert_eq!(memory.get(DENSE_LIMIT + 5), w(11));
+        assert_eq!(memory.fetch(DENSE_LIMIT + 5), Some(w(11)));
+        assert_eq!(memory.get(DENSE_LIMIT + 20), w(12));
+        assert_eq!(memory.fetch(DENSE_LIMIT + 19), None);
+        assert_eq!(
+            memory.cells()[DENSE_LIMIT + 4..],
+            [
+                (DENSE_LIMIT + 4, w(1)),
+                (DENSE_LIMIT + 5, w(11)),
+                (DENSE_LIMIT + 6, w(1)),
+                (DENSE_LIMIT + 7, w(1)),
+                (DENSE_LIMIT + 8, w(1)),
+                (DENSE_LIMIT + 9, w(1)),
+                (DENSE_LIMIT + 20, w(12)),
+            ]
+        );
+    }
+}
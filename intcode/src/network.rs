@@ -0,0 +1,502 @@
+use crate::budget::Budget;
+use crate::memory::{Memory, PagedMemory};
+use crate::vm::{State, Vm};
+use crate::word::{DefaultWord, Word};
+use crate::VmError;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
+
+/// A VM in a network failed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NetworkError<W = DefaultWord> {
+    pub node: usize,
+    pub error: VmError<W>,
+}
+
+impl<W: Word> fmt::Display for NetworkError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node {}: {}", self.node, self.error)
+    }
+}
+
+impl<W: Word> Error for NetworkError<W> {}
+
+/// Something a network can run one instruction at a time.
+pub trait Process<W> {
+    fn step(&mut self) -> Result<Option<State<W>>, VmError<W>>;
+
+    fn add_input(&mut self, value: W);
+}
+
+impl<W: Word, M: Memory<W>> Process<W> for Vm<W, M> {
+    fn step(&mut self) -> Result<Option<State<W>>, VmError<W>> {
+        Vm::step(self)
+    }
+
+    fn add_input(&mut self, value: W) {
+        Vm::add_input(self, value)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Node<W, P> {
+    vm: P,
+    targets: Vec<usize>,
+    outputs: Vec<W>,
+    state: State<W>,
+}
+
+/// VMs whose outputs are wired to each other's inputs. Every output of a node is copied to
+/// each node it's connected to, and also kept so it can be read back after the run.
+#[derive(Clone, Debug)]
+pub struct Network<W: Word = DefaultWord, P: Process<W> = Vm<W, PagedMemory<W>>> {
+    nodes: Vec<Node<W, P>>,
+}
+
+impl<W: Word, P: Process<W>> Default for Network<W, P> {
+    fn default() -> Self {
+        Network { nodes: Vec::new() }
+    }
+}
+
+impl<W: Word, P: Process<W>> Network<W, P> {
+    pub fn new() -> Self {
+        Network::default()
+    }
+
+    /// Connects each VM's output to the next one's input.
+    pub fn pipeline(vms: Vec<P>) -> Self {
+        let mut network = Network::new();
+        for vm in vms {
+            let node = network.add(vm);
+            if node > 0 {
+                network.connect(node - 1, node);
+            }
+        }
+
+        network
+    }
+
+    /// A pipeline whose last VM feeds back into the first.
+    pub fn ring(vms: Vec<P>) -> Self {
+        let mut network = Network::pipeline(vms);
+        if !network.nodes.is_empty() {
+            network.connect(network.nodes.len() - 1, 0);
+        }
+
+        network
+    }
+
+    /// Adds a VM, returning its node number.
+    pub fn add(&mut self, vm: P) -> usize {
+        self.nodes.push(Node {
+            vm,
+            targets: Vec::new(),
+            outputs: Vec::new(),
+            state: State::AwaitingInput,
+        });
+
+        self.nodes.len() - 1
+    }
+
+    /// Sends everything `from` outputs to `to`.
+    pub fn connect(&mut self, from: usize, to: usize) {
+        assert!(to < self.nodes.len(), "no node {}", to);
+        self.nodes[from].targets.push(to);
+    }
+
+    pub fn add_input(&mut self, node: usize, value: W) {
+        self.nodes[node].vm.add_input(value);
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn vm(&self, node: usize) -> &P {
+        &self.nodes[node].vm
+    }
+
+    pub fn vm_mut(&mut self, node: usize) -> &mut P {
+        &mut self.nodes[node].vm
+    }
+
+    /// Everything `node` has output so far.
+    pub fn outputs(&self, node: usize) -> &[W] {
+        &self.nodes[node].outputs
+    }
+
+    /// Each node's state as of the end of the last run.
+    pub fn states(&self) -> Vec<State<W>> {
+        self.nodes.iter().map(|node| node.state.clone()).collect()
+    }
+
+    /// Runs the nodes in turn, each until it blocks, until every node has halted or none can
+    /// make progress. Returns each node's final state: a node left `AwaitingInput` is
+    /// deadlocked, or waiting on input from outside the network.
+    pub fn run(&mut self) -> Result<Vec<State<W>>, NetworkError<W>> {
+        self.run_within(Budget::new())
+    }
+
+    /// Like `run`, but stops once the nodes have executed `budget` instructions between them,
+    /// marking every node that hadn't halted as `BudgetExhausted`.
+    pub fn run_within(&mut self, budget: Budget) -> Result<Vec<State<W>>, NetworkError<W>> {
+        let mut executed = 0;
+
+        loop {
+            let mut progress = false;
+
+            for node in 0..self.nodes.len() {
+                if self.nodes[node].state == State::Halted {
+                    continue;
+                }
+
+                loop {
+                    if budget.exhausted(executed) {
+                        for node in &mut self.nodes {
+                            if node.state != State::Halted {
+                                node.state = State::BudgetExhausted;
+                            }
+                        }
+                        return Ok(self.states());
+                    }
+
+                    match self.nodes[node].vm.step() {
+                        Ok(None) => {}
+                        Ok(Some(State::Output(value))) => self.deliver(node, value),
+                        Ok(Some(state)) => {
+                            self.nodes[node].state = state;
+                            break;
+                        }
+                        Err(error) => return Err(NetworkError { node, error }),
+                    }
+
+                    executed += 1;
+                    progress = true;
+                }
+            }
+
+            if !progress {
+                return Ok(self.states());
+            }
+        }
+    }
+
+    fn deliver(&mut self, from: usize, value: W) {
+        for i in 0..self.nodes[from].targets.len() {
+            let to = self.nodes[from].targets[i];
+            self.nodes[to].vm.add_input(value.clone());
+        }
+        self.nodes[from].outputs.push(value);
+    }
+}
+
+impl<W: Word + Send, P: Process<W> + Send> Network<W, P> {
+    /// Like `run`, but with every VM on its own thread, connected by channels holding up to
+    /// `capacity` values. Once every node has halted or is waiting on an empty channel the
+    /// waiting nodes are shut down, so the results match `run`. A node blocked on a full
+    /// channel isn't waiting, so a cycle of full channels hangs: size `capacity` for the
+    /// programs being run.
+    pub fn run_threaded(&mut self, capacity: usize) -> Result<Vec<State<W>>, NetworkError<W>> {
+        assert!(capacity > 0, "channels need room for at least one value");
+        let (inboxes, receivers): (Vec<_>, Vec<_>) = (0..self.nodes.len())
+            .map(|_| mpsc::sync_channel(capacity))
+            .unzip();
+        let scheduler = Scheduler {
+            inboxes,
+            counts: Mutex::new(Counts {
+                idle: 0,
+                pending: vec![0; self.nodes.len()],
+                finished: vec![false; self.nodes.len()],
+            }),
+            stopped: AtomicBool::new(false),
+            error: Mutex::new(None),
+        };
+
+        thread::scope(|scope| {
+            for (index, (node, inbox)) in self.nodes.iter_mut().zip(receivers).enumerate() {
+                let scheduler = &scheduler;
+                scope.spawn(move || scheduler.work(index, node, inbox));
+            }
+        });
+
+        match scheduler.error.into_inner().unwrap() {
+            Some(error) => Err(error),
+            None => Ok(self.states()),
+        }
+    }
+}
+
+enum Message<W> {
+    Value(W),
+    Shutdown,
+}
+
+struct Counts {
+    /// Nodes that have finished or are waiting on an empty channel.
+    idle: usize,
+    /// Values sent to each node that it hasn't received yet.
+    pending: Vec<usize>,
+    finished: Vec<bool>,
+}
+
+impl Counts {
+    fn deadlocked(&self) -> bool {
+        self.idle == self.pending.len()
+            && self
+                .pending
+                .iter()
+                .zip(&self.finished)
+                .all(|(&pending, &finished)| finished || pending == 0)
+    }
+}
+
+struct Scheduler<W> {
+    inboxes: Vec<SyncSender<Message<W>>>,
+    counts: Mutex<Counts>,
+    stopped: AtomicBool,
+    error: Mutex<Option<NetworkError<W>>>,
+}
+
+impl<W: Word + Send> Scheduler<W> {
+    fn work<P: Process<W>>(
+        &self,
+        index: usize,
+        node: &mut Node<W, P>,
+        inbox: Receiver<Message<W>>,
+    ) {
+        if node.state == State::Halted {
+            self.idle(index, true);
+            return;
+        }
+
+        let waiting = loop {
+            if self.stopped.load(Ordering::Relaxed) {
+                node.state = State::AwaitingInput;
+                break false;
+            }
+
+            match node.vm.step() {
+                Ok(None) => {}
+                Ok(Some(State::Output(value))) => {
+                    for &to in &node.targets {
+                        self.send(to, value.clone());
+                    }
+                    node.outputs.push(value);
+                }
+                Ok(Some(State::AwaitingInput)) => match self.receive(index, &inbox) {
+                    Some(value) => node.vm.add_input(value),
+                    None => {
+                        node.state = State::AwaitingInput;
+                        break true;
+                    }
+                },
+                Ok(Some(state)) => {
+                    node.state = state;
+                    break false;
+                }
+                Err(error) => {
+                    *self.error.lock().unwrap() = Some(NetworkError { node: index, error });
+                    self.shutdown();
+                    break false;
+                }
+            }
+        };
+
+        // A node that was shut down while waiting has already been counted.
+        if !waiting {
+            self.idle(index, true);
+        }
+    }
+
+    /// Records that node `index` has stopped running, shutting the network down if that
+    /// leaves nothing that can make progress.
+    fn idle(&self, index: usize, finished: bool) {
+        let deadlocked = {
+            let mut counts = self.counts.lock().unwrap();
+            counts.idle += 1;
+            counts.finished[index] = finished;
+            counts.deadlocked()
+        };
+
+        if deadlocked {
+            self.shutdown();
+        }
+    }
+
+    fn send(&self, to: usize, value: W) {
+        self.counts.lock().unwrap().pending[to] += 1;
+        // Fails once the node has finished, and nothing is waiting for the value any more.
+        let _ = self.inboxes[to].send(Message::Value(value));
+    }
+
+    /// The next value for node `index`, or `None` if the network has shut down.
+    fn receive(&self, index: usize, inbox: &Receiver<Message<W>>) -> Option<W> {
+        let message = match inbox.try_recv() {
+            Ok(message) => message,
+            Err(TryRecvError::Empty) => {
+                self.idle(index, false);
+                let message = inbox.recv().expect("scheduler dropped its channels");
+                if let Message::Value(_) = message {
+                    self.counts.lock().unwrap().idle -= 1;
+                }
+                message
+            }
+            Err(TryRecvError::Disconnected) => unreachable!("scheduler dropped its channels"),
+        };
+
+        match message {
+            Message::Value(value) => {
+                self.counts.lock().unwrap().pending[index] -= 1;
+                Some(value)
+            }
+            Message::Shutdown => None,
+        }
+    }
+
+    /// Wakes every waiting node and stops the running ones.
+    fn shutdown(&self) {
+        if self.stopped.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        for inbox in &self.inboxes {
+            // A node that has finished, or is about to notice `stopped`, doesn't need waking.
+            let _ = inbox.try_send(Message::Shutdown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::word::w;
+
+    const DOUBLER: &str = "3,11,102,2,11,11,4,11,1105,1,0";
+    const FEEDBACK: &str = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,\
+                            1005,28,6,99,0,0,5";
+
+    #[test]
+    fn test_pipeline() {
+        let mut network = Network::pipeline(vec![Vm::from(DOUBLER), Vm::from(DOUBLER)]);
+        network.add_input(0, w(3));
+        network.add_input(0, w(5));
+
+        let states = network.run().unwrap();
+        assert_eq!(states, vec![State::AwaitingInput, State::AwaitingInput]);
+        assert_eq!(network.outputs(1), &[w(12), w(20)]);
+    }
+
+    #[test]
+    fn test_ring() {
+        let amps = [9, 8, 7, 6, 5]
+            .iter()
+            .map(|&phase| {
+                let mut amp = Vm::from(FEEDBACK);
+                amp.add_input(w(phase));
+                amp
+            })
+            .collect();
+        let mut network = Network::ring(amps);
+        network.add_input(0, w(0));
+
+        assert_eq!(network.run().unwrap(), vec![State::Halted; 5]);
+        assert_eq!(network.outputs(4).last(), Some(&w(139629729)));
+    }
+
+    #[test]
+    fn test_graph() {
+        let mut network = Network::new();
+        let source = network.add(Vm::from("104,1,104,2,99"));
+        let left = network.add(Vm::from(DOUBLER));
+        let right = network.add(Vm::from(DOUBLER));
+        network.connect(source, left);
+        network.connect(source, right);
+        network.connect(left, right);
+
+        network.run().unwrap();
+        assert_eq!(network.outputs(left), &[w(2), w(4)]);
+        assert_eq!(network.outputs(right), &[w(2), w(4), w(4), w(8)]);
+    }
+
+    #[test]
+    fn test_errors_and_budget() {
+        let mut network = Network::pipeline(vec![Vm::from("104,1,99"), Vm::from("3,0,42")]);
+        assert_eq!(network.run().unwrap_err().node, 1);
+
+        let mut network = Network::ring(vec![Vm::from("1105,1,0"), Vm::from(DOUBLER)]);
+        assert_eq!(
+            network.run_within(Budget::new().steps(100)).unwrap(),
+            vec![State::BudgetExhausted; 2]
+        );
+    }
+
+    #[test]
+    fn test_halted_nodes() {
+        let mut network = Network::ring(vec![Vm::from("104,1,99"), Vm::from("104,1,99")]);
+        for node in 0..network.len() {
+            network.vm_mut(node).enable_trace();
+        }
+
+        assert_eq!(network.run().unwrap(), vec![State::Halted; 2]);
+        assert_eq!(network.run().unwrap(), vec![State::Halted; 2]);
+        assert_eq!(network.run_threaded(1).unwrap(), vec![State::Halted; 2]);
+        for node in 0..network.len() {
+            assert_eq!(network.vm(node).steps(), 2);
+            assert_eq!(network.vm_mut(node).take_trace().len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_threaded() {
+        let amps = |phases: &[i128]| {
+            phases
+                .iter()
+                .map(|&phase| {
+                    let mut amp = Vm::from(FEEDBACK);
+                    amp.add_input(w(phase));
+                    amp
+                })
+                .collect()
+        };
+        let mut network = Network::ring(amps(&[9, 8, 7, 6, 5]));
+        network.add_input(0, w(0));
+        let mut threaded = network.clone();
+
+        assert_eq!(threaded.run_threaded(1).unwrap(), network.run().unwrap());
+        for node in 0..network.len() {
+            assert_eq!(threaded.outputs(node), network.outputs(node));
+        }
+
+        let mut network = Network::pipeline(vec![Vm::from(DOUBLER), Vm::from(DOUBLER)]);
+        network.add_input(0, w(3));
+        network.add_input(0, w(5));
+        let states = network.run_threaded(1).unwrap();
+        assert_eq!(states, vec![State::AwaitingInput; 2]);
+        assert_eq!(network.outputs(1), &[w(12), w(20)]);
+
+        let mut network = Network::ring(vec![Vm::from(DOUBLER), Vm::from(DOUBLER)]);
+        assert_eq!(
+            network.run_threaded(4).unwrap(),
+            vec![State::AwaitingInput; 2]
+        );
+    }
+
+    #[test]
+    fn test_threaded_errors() {
+        let mut network = Network::pipeline(vec![
+            Vm::from("104,1,99"),
+            Vm::from("3,0,42"),
+            Vm::from("1105,1,0"),
+        ]);
+        assert_eq!(network.run_threaded(8).unwrap_err().node, 1);
+    }
+}
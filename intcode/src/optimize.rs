@@ -0,0 +1,573 @@
+use crate::asm;
+use crate::cfg::Cfg;
+use crate::disasm::{self, Line, Operand};
+use crate::word::Word;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+/// Rewrites `program` into one that produces the same outputs for the same inputs with less
+/// work: arithmetic on constants is folded, jumps on constant conditions are resolved and
+/// threaded through chains of unconditional jumps, and unreachable code, unused data and jumps
+/// to the next instruction are dropped.
+///
+/// Only instructions the program never reads, overwrites or jumps into the middle of are
+/// rewritten, and cells are only moved when every address the program touches is known before
+/// it runs and no instructions overlap, so a program using relative mode comes back unchanged. Like `Cfg::build`, this assumes the program runs as
+/// loaded: cells patched before running may have been folded away.
+pub fn optimize<W: Word>(program: &[W]) -> Vec<W> {
+    let analysis = Analysis::new(program);
+    let mut optimized = program.to_vec();
+    let mut dead = Vec::new();
+
+    for instruction in analysis.instructions.values() {
+        if !analysis.fixed(instruction) {
+            continue;
+        }
+
+        let cells = match (instruction.mnemonic, instruction.operands.as_slice()) {
+            ("add" | "mul" | "lt" | "eq", [a, b, Operand::Position(destination)]) => {
+                let (a, b) = match (analysis.value(a), analysis.value(b)) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => continue,
+                };
+                let result = match instruction.mnemonic {
+                    "add" => a.checked_add(&b),
+                    "mul" => a.checked_mul(&b),
+                    "lt" => Some(W::from((a < b) as i32)),
+                    _ => Some(W::from((a == b) as i32)),
+                };
+                match result {
+                    Some(result) => encode(
+                        "add",
+                        &[
+                            Operand::Immediate(result),
+                            Operand::Immediate(W::from(0)),
+                            Operand::Position(destination.clone()),
+                        ],
+                    ),
+                    None => continue,
+                }
+            }
+            ("jnz" | "jz", [condition, target]) => {
+                let target = analysis
+                    .value(target)
+                    .and_then(|target| target.to_address());
+                match (analysis.taken(instruction), target) {
+                    (Some(false), _) => {
+                        dead.push(instruction.address);
+                        continue;
+                    }
+                    (Some(true), Some(target)) => encode(
+                        "jnz",
+                        &[
+                            Operand::Immediate(W::from(1)),
+                            Operand::Immediate(word(analysis.thread(target))),
+                        ],
+                    ),
+                    (None, Some(target)) => encode(
+                        instruction.mnemonic,
+                        &[
+                            condition.clone(),
+                            Operand::Immediate(word(analysis.thread(target))),
+                        ],
+                    ),
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+
+        optimized[instruction.cells()].clone_from_slice(&cells);
+    }
+
+    if analysis.relocatable() {
+        compact(&analysis, &optimized, &dead)
+    } else {
+        optimized
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Instruction<W> {
+    address: usize,
+    mnemonic: &'static str,
+    operands: Vec<Operand<W>>,
+}
+
+impl<W: Word> Instruction<W> {
+    fn decode(program: &[W], address: usize) -> Option<Self> {
+        match disasm::decode(program, address)? {
+            Line::Instruction {
+                address,
+                mnemonic,
+                operands,
+            } => Some(Instruction {
+                address,
+                mnemonic,
+                operands,
+            }),
+            Line::Data { .. } => None,
+        }
+    }
+
+    fn cells(&self) -> Range<usize> {
+        self.address..self.address + 1 + self.operands.len()
+    }
+
+    fn is_jump(&self) -> bool {
+        matches!(self.mnemonic, "jnz" | "jz")
+    }
+
+    /// Where the instruction always jumps to, if it is a jump on an immediate condition that
+    /// is always taken.
+    fn always_jumps_to(&self) -> Option<usize> {
+        match (self.mnemonic, self.operands.as_slice()) {
+            ("jnz" | "jz", [Operand::Immediate(condition), Operand::Immediate(target)])
+                if condition.is_zero() == (self.mnemonic == "jz") =>
+            {
+                target.to_address()
+            }
+            _ => None,
+        }
+    }
+}
+
+struct Analysis<'a, W> {
+    program: &'a [W],
+    /// The instructions reachable from address 0.
+    instructions: BTreeMap<usize, Instruction<W>>,
+    /// Addresses read or written as data, or `None` if some can't be known before running.
+    reads: Option<BTreeSet<usize>>,
+    writes: Option<BTreeSet<usize>>,
+    /// Whether every jump target and memory access is a known address, and execution never
+    /// runs into a cell that doesn't decode as an instruction.
+    closed: bool,
+    /// Cells that belong to more than one decoded instruction or that a jump lands inside an
+    /// instruction on, or `None` if some jump target can't be known before running.
+    shared: Option<BTreeSet<usize>>,
+}
+
+impl<'a, W: Word> Analysis<'a, W> {
+    fn new(program: &'a [W]) -> Self {
+        let cfg = Cfg::build(program);
+        let mut closed = cfg.blocks.values().all(|block| {
+            let stops = matches!(
+                block.instructions.last(),
+                Some(Line::Instruction {
+                    mnemonic: "hlt",
+                    ..
+                })
+            );
+            !block.indirect
+                && (stops || !block.successors.is_empty())
+                && block
+                    .successors
+                    .iter()
+                    .all(|edge| cfg.blocks.contains_key(&edge.target()))
+        });
+        let instructions: BTreeMap<_, _> = cfg
+            .blocks
+            .values()
+            .flat_map(|block| &block.instructions)
+            .filter_map(|line| Instruction::decode(program, line.address()))
+            .map(|instruction| (instruction.address, instruction))
+            .collect();
+
+        let mut reads = Some(BTreeSet::new());
+        let mut writes = Some(BTreeSet::new());
+        for instruction in instructions.values() {
+            closed &= instruction.mnemonic != "arb";
+            let operation = disasm::operation(instruction.mnemonic).unwrap();
+
+            for (parameter, operand) in instruction.operands.iter().enumerate() {
+                let accesses = if asm::writes(operation, parameter) {
+                    &mut writes
+                } else {
+                    &mut reads
+                };
+
+                match operand {
+                    Operand::Position(address) => match address.to_address() {
+                        Some(address) => {
+                            if let Some(accesses) = accesses {
+                                accesses.insert(address);
+                            }
+                        }
+                        None => closed = false,
+                    },
+                    Operand::Relative(_) => {
+                        *accesses = None;
+                        closed = false;
+                    }
+                    Operand::Immediate(target) if instruction.is_jump() && parameter == 1 => {
+                        closed &= target.to_address().is_some();
+                    }
+                    Operand::Immediate(_) => {}
+                }
+            }
+        }
+
+        let mut analysis = Analysis {
+            program,
+            instructions,
+            reads,
+            writes,
+            closed,
+            shared: None,
+        };
+        analysis.shared = analysis.shared();
+        analysis
+    }
+
+    fn shared(&self) -> Option<BTreeSet<usize>> {
+        let mut covered = BTreeSet::new();
+        let mut shared = BTreeSet::new();
+        for address in self.code() {
+            if !covered.insert(address) {
+                shared.insert(address);
+            }
+        }
+
+        for instruction in self.instructions.values().filter(|i| i.is_jump()) {
+            let target = self.value(instruction.operands.get(1)?)?.to_address()?;
+            let inside = self
+                .instructions
+                .values()
+                .any(|other| other.address < target && target < other.cells().end);
+            if inside {
+                shared.insert(target);
+            }
+        }
+
+        Some(shared)
+    }
+
+    fn code(&self) -> impl Iterator<Item = usize> + '_ {
+        self.instructions.values().flat_map(Instruction::cells)
+    }
+
+    fn read(&self, address: usize) -> bool {
+        self.reads
+            .as_ref()
+            .is_none_or(|reads| reads.contains(&address))
+    }
+
+    fn written(&self, address: usize) -> bool {
+        self.writes
+            .as_ref()
+            .is_none_or(|writes| writes.contains(&address))
+    }
+
+    /// Whether the instruction always runs as it appears in the program, and can be rewritten
+    /// without anything noticing. Execution must never start partway through it.
+    fn fixed(&self, instruction: &Instruction<W>) -> bool {
+        let shared = match &self.shared {
+            Some(shared) => shared,
+            None => return false,
+        };
+        instruction.cells().all(|address| {
+            !self.read(address) && !self.written(address) && !shared.contains(&address)
+        })
+    }
+
+    /// Whether the code can be moved: every address is known, no code is accessed as data and
+    /// no two instructions overlap.
+    fn relocatable(&self) -> bool {
+        self.closed
+            && self.shared.as_ref().is_some_and(BTreeSet::is_empty)
+            && self
+                .code()
+                .all(|address| !self.read(address) && !self.written(address))
+    }
+
+    /// The operand's value if it's the same every time the instruction runs.
+    fn value(&self, operand: &Operand<W>) -> Option<W> {
+        match operand {
+            Operand::Immediate(value) => Some(value.clone()),
+            Operand::Position(address) => {
+                let address = address.to_address()?;
+                if self.written(address) {
+                    return None;
+                }
+                Some(
+                    self.program
+                        .get(address)
+                        .cloned()
+                        .unwrap_or_else(|| W::from(0)),
+                )
+            }
+            Operand::Relative(_) => None,
+        }
+    }
+
+    /// Whether a fixed jump is always or never taken, or `None` if it depends on the run.
+    fn taken(&self, instruction: &Instruction<W>) -> Option<bool> {
+        let condition = self.value(instruction.operands.first()?)?;
+        Some(condition.is_zero() == (instruction.mnemonic == "jz"))
+    }
+
+    /// Follows fixed, always-taken jumps from `target` to where execution really continues.
+    fn thread(&self, mut target: usize) -> usize {
+        let mut visited = BTreeSet::new();
+        while let Some(instruction) = self.instructions.get(&target) {
+            if !instruction.is_jump() || !self.fixed(instruction) || !visited.insert(target) {
+                break;
+            }
+            let next = match (self.taken(instruction), instruction.operands.get(1)) {
+                (Some(true), Some(next)) => self.value(next).and_then(|next| next.to_address()),
+                _ => None,
+            };
+            match next {
+                Some(next) => target = next,
+                None => break,
+            }
+        }
+
+        target
+    }
+}
+
+/// Drops unreachable code, unused data, never-taken jumps and jumps to the next instruction,
+/// moving everything else down and adjusting the addresses that refer to it.
+fn compact<W: Word>(analysis: &Analysis<W>, program: &[W], dead: &[usize]) -> Vec<W> {
+    let code: BTreeSet<usize> = analysis.code().collect();
+    let instructions: BTreeMap<usize, Instruction<W>> = analysis
+        .instructions
+        .keys()
+        .filter_map(|&address| Instruction::decode(program, address))
+        .map(|instruction| (instruction.address, instruction))
+        .collect();
+
+    let mut removed: BTreeSet<usize> = (0..program.len())
+        .filter(|&address| {
+            !code.contains(&address) && !analysis.read(address) && !analysis.written(address)
+        })
+        .collect();
+    for address in dead {
+        removed.extend(instructions[address].cells());
+    }
+
+    loop {
+        let skips = instructions.values().find(|instruction| {
+            let end = instruction.cells().end;
+            !removed.contains(&instruction.address)
+                && instruction.always_jumps_to().is_some_and(|target| {
+                    target >= end && (end..target).all(|a| removed.contains(&a))
+                })
+        });
+        match skips {
+            Some(instruction) => removed.extend(instruction.cells()),
+            None => break,
+        }
+    }
+
+    let mut below = Vec::with_capacity(program.len() + 1);
+    below.push(0);
+    for address in 0..program.len() {
+        below.push(below[address] + removed.contains(&address) as usize);
+    }
+    let relocate = |address: &W| {
+        let address = address.to_address().unwrap();
+        word(address - below[address.min(program.len())])
+    };
+
+    let mut compacted = Vec::with_capacity(program.len() - removed.len());
+    let mut address = 0;
+    while address < program.len() {
+        if removed.contains(&address) {
+            address += 1;
+            continue;
+        }
+
+        match instructions.get(&address) {
+            Some(instruction) => {
+                let operands: Vec<Operand<W>> = instruction
+                    .operands
+                    .iter()
+                    .enumerate()
+                    .map(|(parameter, operand)| match operand {
+                        Operand::Position(address) => Operand::Position(relocate(address)),
+                        Operand::Immediate(target) if instruction.is_jump() && parameter == 1 => {
+                            Operand::Immediate(relocate(target))
+                        }
+                        operand => operand.clone(),
+                    })
+                    .collect();
+                compacted.extend(encode(instruction.mnemonic, &operands));
+                address = instruction.cells().end;
+            }
+            None => {
+                compacted.push(program[address].clone());
+                address += 1;
+            }
+        }
+    }
+
+    compacted
+}
+
+fn encode<W: Word>(mnemonic: &str, operands: &[Operand<W>]) -> Vec<W> {
+    let modes = operands.iter().rev().fold(0, |modes, operand| {
+        modes * 10
+            + match operand {
+                Operand::Position(_) => 0,
+                Operand::Immediate(_) => 1,
+                Operand::Relative(_) => 2,
+            }
+    });
+    let opcode = modes * 100 + disasm::operation(mnemonic).unwrap() as i32;
+
+    let mut cells = vec![W::from(opcode)];
+    cells.extend(operands.iter().map(|operand| match operand {
+        Operand::Position(value) | Operand::Immediate(value) | Operand::Relative(value) => {
+            value.clone()
+        }
+    }));
+    cells
+}
+
+fn word<W: Word>(address: usize) -> W {
+    W::try_from(address as i128)
+        .ok()
+        .expect("addresses come from words")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asm::{assemble, emit};
+    use crate::memory::{Memory, PagedMemory};
+    use crate::vm::{parse, State, Vm};
+    use crate::word::{w, DefaultWord};
+
+    // AoC day 5: outputs 999, 1000 or 1001 as the input is below, equal to or above 8.
+    const COMPARE: &str = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,\
+        1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+
+    fn outputs(program: &[DefaultWord], input: DefaultWord) -> Vec<DefaultWord> {
+        let mut vm: Vm = Vm::new(PagedMemory::from_program(program.to_vec()));
+        vm.add_input(input);
+
+        let mut outputs = Vec::new();
+        while let Ok(State::Output(value)) = vm.execute() {
+            outputs.push(value);
+        }
+        outputs
+    }
+
+    fn assert_equivalent(program: &[DefaultWord], optimized: &[DefaultWord], inputs: &[i128]) {
+        for &input in inputs {
+            assert_eq!(
+                outputs(optimized, w(input)),
+                outputs(program, w(input)),
+                "input {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_fold_and_compact() {
+        let program = assemble::<DefaultWord>(
+            "
+                    add #2, #3, [x]
+                    jz #0, #skip
+                    out #99
+            skip:   mul [x], #4, [y]
+                    jnz #0, #never
+                    out [y]
+                    hlt
+            never:  out #1
+                    hlt
+            x:      data 0
+            y:      data 0
+            ",
+        )
+        .unwrap();
+        let optimized = optimize(&program);
+
+        assert_eq!(emit(&optimized), "1101,5,0,11,1002,11,4,12,4,12,99,0,0");
+        assert_equivalent(&program, &optimized, &[0]);
+    }
+
+    #[test]
+    fn test_threading() {
+        let program = assemble::<DefaultWord>(
+            "
+                    in [n]
+                    jnz [n], #a
+                    out #0
+                    hlt
+            a:      jz #0, #b
+                    hlt
+            b:      out #1
+                    hlt
+            n:      data 0
+            ",
+        )
+        .unwrap();
+        let optimized = optimize(&program);
+
+        assert_eq!(emit(&optimized), "3,11,1005,11,8,104,0,99,104,1,99,0");
+        assert_equivalent(&program, &optimized, &[0, 1]);
+    }
+
+    #[test]
+    fn test_compare() {
+        let program: Vec<DefaultWord> = parse(COMPARE);
+        let optimized = optimize(&program);
+
+        assert!(optimized.len() < program.len());
+        assert_equivalent(&program, &optimized, &[-3, 7, 8, 9, 100]);
+    }
+
+    #[test]
+    fn test_indirect_jump() {
+        // The jump through [target] keeps everything in place, but it can still be resolved.
+        let program = assemble::<DefaultWord>(
+            "
+                    add #2, #3, [x]
+                    jz #0, [target]
+                    out #99
+            dest:   out [x]
+                    hlt
+            target: data dest
+            x:      data 0
+            ",
+        )
+        .unwrap();
+        let optimized = optimize(&program);
+
+        assert_eq!(emit(&optimized), "1101,5,0,13,1105,1,9,104,99,4,13,99,9,0");
+        assert_equivalent(&program, &optimized, &[0]);
+    }
+
+    #[test]
+    fn test_unchanged() {
+        // Relative reads could touch any cell, the input overwrites the jump condition, and
+        // adding the input to [6] turns it into an instruction.
+        for source in [
+            "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99",
+            "3,3,1105,0,7,104,1,99",
+            "3,9,1,9,6,6,1100,42,99,0",
+        ] {
+            let program: Vec<DefaultWord> = parse(source);
+            assert_eq!(optimize(&program), program);
+        }
+    }
+
+    #[test]
+    fn test_overlapping_instructions() {
+        // Both jump into the middle of the `add` at 0: the first to an `out #5` and a `hlt`
+        // inside it, the second to an `in` that waits for input.
+        let program: Vec<DefaultWord> = parse("1101,104,5,99,1105,1,1");
+        let optimized = optimize(&program);
+        assert_eq!(outputs(&optimized, w(0)), vec![w(5)]);
+        assert_equivalent(&program, &optimized, &[0]);
+
+        let program: Vec<DefaultWord> = parse("1101,2,3,9,1105,1,2,99,99,0");
+        let mut vm: Vm = Vm::new(PagedMemory::from_program(optimize(&program)));
+        assert_eq!(vm.execute(), Ok(State::AwaitingInput));
+        assert_equivalent(&program, &optimize(&program), &[0]);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::disasm;
+use crate::word::Word;
+use std::collections::HashMap;
+
+/// Execution counts collected by a VM with profiling enabled. Reads only count operands that
+/// refer to memory, so immediate operands don't show up as hotspots.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Profile {
+    pub instructions: HashMap<usize, u64>,
+    pub opcodes: HashMap<usize, u64>,
+    pub reads: HashMap<usize, u64>,
+    pub writes: HashMap<usize, u64>,
+}
+
+impl Profile {
+    pub(crate) fn instruction(&mut self, address: usize, operation: usize) {
+        *self.instructions.entry(address).or_insert(0) += 1;
+        *self.opcodes.entry(operation).or_insert(0) += 1;
+    }
+
+    pub(crate) fn read(&mut self, address: usize) {
+        *self.reads.entry(address).or_insert(0) += 1;
+    }
+
+    pub(crate) fn write(&mut self, address: usize) {
+        *self.writes.entry(address).or_insert(0) += 1;
+    }
+
+    pub fn executed(&self) -> u64 {
+        self.instructions.values().sum()
+    }
+
+    /// Lists the `limit` busiest instructions and memory cells, and every opcode, busiest
+    /// first. `program` is used to disassemble the hot instructions.
+    pub fn report<W: Word>(&self, program: &[W], limit: usize) -> String {
+        let total = self.executed();
+        let mut lines = vec![format!("{} instructions executed", total)];
+
+        lines.push("hottest instructions:".to_string());
+        for (address, count) in ranked(&self.instructions).into_iter().take(limit) {
+            let instruction = match disasm::decode(program, address) {
+                Some(line) => line.to_string(),
+                None => format!("{:04}: ???", address),
+            };
+            lines.push(format!(
+                "{:>12} {:>6.2}%  {}",
+                count,
+                percent(count, total),
+                instruction
+            ));
+        }
+
+        lines.push("opcodes:".to_string());
+        for (operation, count) in ranked(&self.opcodes) {
+            lines.push(format!(
+                "{:>12} {:>6.2}%  {}",
+                count,
+                percent(count, total),
+                disasm::mnemonic(operation).unwrap_or("???")
+            ));
+        }
+
+        for (title, counts) in &[
+            ("memory reads:", &self.reads),
+            ("memory writes:", &self.writes),
+        ] {
+            lines.push(title.to_string());
+            for (address, count) in ranked(counts).into_iter().take(limit) {
+                lines.push(format!("{:>12}          {:04}", count, address));
+            }
+        }
+
+        lines.join("\n")
+    }
+
+    /// The disassembly of `program` with each instruction prefixed by its execution count.
+    pub fn annotate<W: Word>(&self, program: &[W]) -> String {
+        disasm::disassemble(program)
+            .iter()
+            .map(|line| match self.instructions.get(&line.address()) {
+                Some(count) => format!("{:>12}  {}\n", count, line),
+                None => format!("{:>12}  {}\n", "", line),
+            })
+            .collect()
+    }
+}
+
+fn ranked(counts: &HashMap<usize, u64>) -> Vec<(usize, u64)> {
+    let mut ranked: Vec<(usize, u64)> = counts
+        .iter()
+        .map(|(&address, &count)| (address, count))
+        .collect();
+    ranked.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::{parse, State, Vm};
+    use crate::word::DefaultWord;
+
+    const COUNTDOWN: &str = "1101,0,3,13,1001,13,-1,13,1005,13,4,99,0,0";
+
+    #[test]
+    fn test_counts() {
+        let mut vm = Vm::from(COUNTDOWN);
+        vm.enable_profile();
+        assert_eq!(vm.execute(), Ok(State::Halted));
+
+        let profile = vm.take_profile();
+        assert_eq!(profile.executed(), 8);
+        assert_eq!(profile.instructions[&0], 1);
+        assert_eq!(profile.instructions[&4], 3);
+        assert_eq!(profile.instructions[&8], 3);
+        assert_eq!(profile.opcodes[&1], 4);
+        assert_eq!(profile.opcodes[&5], 3);
+        assert_eq!(profile.opcodes[&99], 1);
+        assert_eq!(profile.reads[&13], 6);
+        assert_eq!(profile.writes[&13], 4);
+        assert_eq!(vm.take_profile(), Profile::default());
+    }
+
+    #[test]
+    fn test_report() {
+        let program: Vec<DefaultWord> = parse(COUNTDOWN);
+        let mut vm = Vm::from(COUNTDOWN);
+        vm.enable_profile();
+        vm.execute().unwrap();
+        let profile = vm.take_profile();
+
+        let report = profile.report(&program, 2);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "8 instructions executed");
+        assert_eq!(lines[1], "hottest instructions:");
+        assert_eq!(lines[2], "           3  37.50%  0004: add [13], #-1, [13]");
+        assert_eq!(lines[3], "           3  37.50%  0008: jnz [13], #4");
+        assert_eq!(lines[5], "           4  50.00%  add");
+
+        let annotated = profile.annotate(&program);
+        assert!(annotated.starts_with("           1  0000: add #0, #3, [13]\n"));
+        assert!(annotated.contains("              0012: data 0\n"));
+    }
+}
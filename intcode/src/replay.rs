@@ -0,0 +1,330 @@
+use crate::budget::Budget;
+use crate::memory::Memory;
+use crate::vm::{State, Vm};
+use crate::word::{decimal, DefaultWord, Word};
+use crate::VmError;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// A value a recording VM read or wrote, with the step of the instruction that did it.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", bound = "W: Word")]
+pub enum Event<W = DefaultWord> {
+    Input {
+        step: u64,
+        #[serde(with = "decimal")]
+        value: W,
+    },
+    Output {
+        step: u64,
+        #[serde(with = "decimal")]
+        value: W,
+    },
+}
+
+impl<W: Word> fmt::Display for Event<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Input { step, value } => write!(f, "input {} at step {}", value, step),
+            Event::Output { step, value } => write!(f, "output {} at step {}", value, step),
+        }
+    }
+}
+
+/// What a replayed VM did instead of the logged event.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action<W = DefaultWord> {
+    Event(Event<W>),
+    Stopped { step: u64, state: State<W> },
+    Failed { step: u64, error: VmError<W> },
+}
+
+impl<W: Word> fmt::Display for Action<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Event(event) => write!(f, "{}", event),
+            Action::Stopped {
+                step,
+                state: State::AwaitingInput,
+            } => write!(f, "asked for input at step {}", step),
+            Action::Stopped {
+                step,
+                state: State::BudgetExhausted,
+            } => write!(f, "ran out of budget at step {}", step),
+            Action::Stopped { step, state } => write!(f, "stopped {:?} at step {}", state, step),
+            Action::Failed { step, error } => write!(f, "failed at step {}: {}", step, error),
+        }
+    }
+}
+
+/// The first point where a replay didn't follow the log.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence<W = DefaultWord> {
+    /// Index of the first log entry that wasn't matched.
+    pub index: usize,
+    /// `None` if the log had already run out.
+    pub expected: Option<Event<W>>,
+    pub actual: Action<W>,
+}
+
+impl<W: Word> fmt::Display for Divergence<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.expected {
+            Some(expected) => write!(
+                f,
+                "event {}: expected {}, but the vm {}",
+                self.index, expected, self.actual
+            ),
+            None => write!(
+                f,
+                "event {}: expected nothing more, but the vm {}",
+                self.index, self.actual
+            ),
+        }
+    }
+}
+
+impl<W: Word> Error for Divergence<W> {}
+
+/// Runs `vm` on the inputs from `log`, checking that it reads them at the logged steps and
+/// produces the logged outputs. Returns the state the VM stops in once the log is used up, or
+/// `State::BudgetExhausted` if `budget` runs out after that. Events the VM was already
+/// recording are left as they were.
+pub fn replay<W: Word, M: Memory<W>>(
+    vm: &mut Vm<W, M>,
+    log: &[Event<W>],
+    budget: Budget,
+) -> Result<State<W>, Divergence<W>> {
+    let recording = vm.recording.replace(Vec::new());
+    let result = follow(vm, log, budget);
+    vm.recording = recording;
+
+    result
+}
+
+fn follow<W: Word, M: Memory<W>>(
+    vm: &mut Vm<W, M>,
+    log: &[Event<W>],
+    budget: Budget,
+) -> Result<State<W>, Divergence<W>> {
+    let mut index = 0;
+    let mut executed = 0;
+
+    loop {
+        if budget.exhausted(executed) {
+            return match log.get(index) {
+                None => Ok(State::BudgetExhausted),
+                expected => Err(Divergence {
+                    index,
+                    expected: expected.cloned(),
+                    actual: Action::Stopped {
+                        step: vm.steps(),
+                        state: State::BudgetExhausted,
+                    },
+                }),
+            };
+        }
+
+        let result = vm.step();
+
+        for event in vm.take_recording() {
+            if log.get(index) != Some(&event) {
+                return Err(Divergence {
+                    index,
+                    expected: log.get(index).cloned(),
+                    actual: Action::Event(event),
+                });
+            }
+            index += 1;
+        }
+
+        let step = vm.steps();
+        let state = match result {
+            Ok(None) | Ok(Some(State::Output(_))) => {
+                executed += 1;
+                continue;
+            }
+            Ok(Some(state)) => state,
+            Err(error) => {
+                return Err(Divergence {
+                    index,
+                    expected: log.get(index).cloned(),
+                    actual: Action::Failed { step, error },
+                })
+            }
+        };
+
+        match log.get(index) {
+            None => return Ok(state),
+            Some(Event::Input {
+                step: expected,
+                value,
+            }) if state == State::AwaitingInput && *expected == step => vm.add_input(value.clone()),
+            expected => {
+                return Err(Divergence {
+                    index,
+                    expected: expected.cloned(),
+                    actual: Action::Stopped { step, state },
+                })
+            }
+        }
+    }
+}
+
+/// Writes events as JSON lines.
+pub fn write<T: Write, W: Word>(mut writer: T, log: &[Event<W>]) -> io::Result<()> {
+    for event in log {
+        serde_json::to_writer(&mut writer, event)?;
+        writer.write_all(b"\n")?;
+    }
+
+    writer.flush()
+}
+
+pub fn read<R: BufRead, W: Word>(reader: R) -> io::Result<Vec<Event<W>>> {
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::network::Network;
+    use crate::word::w;
+
+    const DOUBLER: &str = "3,11,102,2,11,11,4,11,1105,1,0";
+
+    #[test]
+    fn test_recording() {
+        let mut vm = Vm::from(DOUBLER);
+        vm.enable_recording();
+        vm.add_input(w(3));
+        vm.add_input(w(5));
+
+        assert_eq!(vm.execute(), Ok(State::Output(w(6))));
+        assert_eq!(vm.execute(), Ok(State::Output(w(10))));
+        assert_eq!(vm.execute(), Ok(State::AwaitingInput));
+        assert_eq!(
+            vm.take_recording(),
+            vec![
+                Event::Input {
+                    step: 0,
+                    value: w(3)
+                },
+                Event::Output {
+                    step: 2,
+                    value: w(6)
+                },
+                Event::Input {
+                    step: 4,
+                    value: w(5)
+                },
+                Event::Output {
+                    step: 6,
+                    value: w(10)
+                },
+            ]
+        );
+        assert!(vm.take_recording().is_empty());
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut network = Network::pipeline(vec![Vm::from(DOUBLER), Vm::from(DOUBLER)]);
+        network.vm_mut(1).enable_recording();
+        network.add_input(0, w(3));
+        network.add_input(0, w(5));
+        network.run().unwrap();
+
+        let mut log = Vec::new();
+        write(&mut log, &network.vm_mut(1).take_recording()).unwrap();
+        let log = read(log.as_slice()).unwrap();
+        assert_eq!(log.len(), 4);
+        assert_eq!(
+            replay(&mut Vm::from(DOUBLER), &log, Budget::new()),
+            Ok(State::AwaitingInput)
+        );
+
+        let divergence = replay(
+            &mut Vm::from("3,11,1002,11,3,11,4,11,1105,1,0"),
+            &log,
+            Budget::new(),
+        );
+        assert_eq!(
+            divergence,
+            Err(Divergence {
+                index: 1,
+                expected: Some(Event::Output {
+                    step: 2,
+                    value: w(12)
+                }),
+                actual: Action::Event(Event::Output {
+                    step: 2,
+                    value: w(18)
+                }),
+            })
+        );
+
+        let divergence = replay(&mut Vm::from("3,11,104,0,99"), &log, Budget::new()).unwrap_err();
+        assert_eq!(divergence.index, 1);
+        assert_eq!(
+            divergence.to_string(),
+            "event 1: expected output 12 at step 2, but the vm output 0 at step 1"
+        );
+
+        let divergence =
+            replay(&mut Vm::from("1101,0,0,0,3,11,99"), &log, Budget::new()).unwrap_err();
+        assert_eq!(
+            divergence.actual,
+            Action::Stopped {
+                step: 1,
+                state: State::AwaitingInput
+            }
+        );
+    }
+
+    #[test]
+    fn test_budget() {
+        let log = vec![
+            Event::Input {
+                step: 0,
+                value: w(3),
+            },
+            Event::Output {
+                step: 2,
+                value: w(6),
+            },
+        ];
+
+        let mut vm = Vm::from(DOUBLER);
+        vm.enable_recording();
+        vm.add_input(w(1));
+        assert_eq!(vm.execute(), Ok(State::Output(w(2))));
+        let recorded = vm.recording.clone();
+
+        let divergence = replay(&mut Vm::from(DOUBLER), &log, Budget::new().steps(2)).unwrap_err();
+        assert_eq!(divergence.index, 1);
+        assert_eq!(
+            divergence.to_string(),
+            "event 1: expected output 6 at step 2, but the vm ran out of budget at step 2"
+        );
+
+        let mut spinner = Vm::from("3,11,1101,0,0,12,104,6,1105,1,8");
+        assert_eq!(
+            replay(&mut spinner, &log, Budget::new().steps(10)),
+            Ok(State::BudgetExhausted)
+        );
+
+        assert!(replay(&mut vm, &[], Budget::new()).is_ok());
+        assert_eq!(vm.recording, recorded);
+
+        let mut vm = Vm::from(DOUBLER);
+        replay(&mut vm, &log, Budget::new()).unwrap();
+        assert_eq!(vm.recording, None);
+    }
+}
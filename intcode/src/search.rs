@@ -0,0 +1,243 @@
+use crate::budget::Budget;
+use crate::memory::{Memory, PagedMemory};
+use crate::vm::{State, Vm};
+use crate::word::{DefaultWord, Word};
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// The number of assignments doesn't fit in a `usize`, so they can't be enumerated.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TooManyAssignments;
+
+impl fmt::Display for TooManyAssignments {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "too many assignments to search")
+    }
+}
+
+impl Error for TooManyAssignments {}
+
+/// Tries every combination of values for a set of memory cells, looking for runs that halt
+/// with memory or output meeting a condition. Each run starts from a copy of the program VM,
+/// so queue any input it needs before building the search.
+#[derive(Clone, Debug)]
+pub struct Search<W: Word = DefaultWord, M: Memory<W> = PagedMemory<W>> {
+    program: Vm<W, M>,
+    cells: Vec<(usize, Vec<W>)>,
+    budget: Budget,
+    threads: usize,
+}
+
+impl<W, M> Search<W, M>
+where
+    W: Word + Send + Sync,
+    M: Memory<W> + Send + Sync,
+{
+    pub fn new(program: Vm<W, M>) -> Self {
+        Search {
+            program,
+            cells: Vec::new(),
+            budget: Budget::new(),
+            threads: 1,
+        }
+    }
+
+    /// Tries each of `values` at `address`. Assignments list the values in the order the
+    /// cells were added.
+    pub fn vary<I: IntoIterator<Item = W>>(mut self, address: usize, values: I) -> Self {
+        self.cells.push((address, values.into_iter().collect()));
+        self
+    }
+
+    /// Limits each run's steps. Runs that don't halt within the limit are skipped. A deadline
+    /// is shared by the whole search, so every run started after it passes is skipped too.
+    pub fn budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Spreads the runs over `threads` threads.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// How many assignments there are to try, or `None` if that overflows a `usize`.
+    pub fn len(&self) -> Option<usize> {
+        if self.cells.iter().any(|(_, values)| values.is_empty()) {
+            return Some(0);
+        }
+
+        self.cells
+            .iter()
+            .try_fold(1usize, |len, (_, values)| len.checked_mul(values.len()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    /// Every assignment whose run satisfies `condition`, in the order they're enumerated: the
+    /// last cell added varies fastest.
+    pub fn all<F>(&self, condition: F) -> Result<Vec<Vec<W>>, TooManyAssignments>
+    where
+        F: Fn(&Vm<W, M>, &[W]) -> bool + Sync,
+    {
+        let found = Mutex::new(Vec::new());
+        self.each(|index| {
+            if self.satisfies(index, &condition) {
+                found.lock().unwrap().push(index);
+            }
+            true
+        })?;
+
+        let mut found = found.into_inner().unwrap();
+        found.sort_unstable();
+        Ok(found
+            .into_iter()
+            .map(|index| self.assignment(index))
+            .collect())
+    }
+
+    /// The first assignment, in enumeration order, whose run satisfies `condition`, stopping
+    /// as soon as it's known.
+    pub fn first<F>(&self, condition: F) -> Result<Option<Vec<W>>, TooManyAssignments>
+    where
+        F: Fn(&Vm<W, M>, &[W]) -> bool + Sync,
+    {
+        let first = AtomicUsize::new(usize::MAX);
+        self.each(|index| {
+            if index > first.load(Ordering::Relaxed) {
+                return false;
+            }
+            if self.satisfies(index, &condition) {
+                first.fetch_min(index, Ordering::Relaxed);
+                return false;
+            }
+            true
+        })?;
+
+        Ok(match first.into_inner() {
+            usize::MAX => None,
+            index => Some(self.assignment(index)),
+        })
+    }
+
+    /// Calls `visit` with every assignment index, each thread taking every `threads`th one in
+    /// order, until it returns false.
+    fn each<F: Fn(usize) -> bool + Sync>(&self, visit: F) -> Result<(), TooManyAssignments> {
+        let len = self.len().ok_or(TooManyAssignments)?;
+        thread::scope(|scope| {
+            for thread in 0..self.threads {
+                let visit = &visit;
+                scope.spawn(move || {
+                    for index in (thread..len).step_by(self.threads) {
+                        if !visit(index) {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(())
+    }
+
+    fn satisfies<F: Fn(&Vm<W, M>, &[W]) -> bool>(&self, index: usize, condition: &F) -> bool {
+        let mut vm = self.program.clone();
+        for ((address, _), value) in self.cells.iter().zip(self.assignment(index)) {
+            vm.set(*address, value);
+        }
+
+        let mut outputs = Vec::new();
+        match vm.run_within(&mut None, &mut outputs, self.budget) {
+            Ok(State::Halted) => condition(&vm, &outputs),
+            _ => false,
+        }
+    }
+
+    fn assignment(&self, mut index: usize) -> Vec<W> {
+        let mut assignment = vec![W::default(); self.cells.len()];
+        for (i, (_, values)) in self.cells.iter().enumerate().rev() {
+            assignment[i] = values[index % values.len()].clone();
+            index /= values.len();
+        }
+
+        assignment
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::word::w;
+
+    const SUM: &str = "1101,0,0,9,4,9,99,0,0,0";
+
+    #[test]
+    fn test_memory() {
+        let search = Search::new(Vm::from("1,5,6,0,99,0,0"))
+            .vary(5, (0..5).map(w))
+            .vary(6, (0..5).map(w));
+
+        assert_eq!(search.len(), Some(25));
+        assert_eq!(
+            search.all(|vm, _| vm.get(0) == w(3)).unwrap(),
+            vec![
+                vec![w(0), w(3)],
+                vec![w(1), w(2)],
+                vec![w(2), w(1)],
+                vec![w(3), w(0)]
+            ]
+        );
+        assert_eq!(
+            search.first(|vm, _| vm.get(0) == w(3)).unwrap(),
+            Some(vec![w(0), w(3)])
+        );
+        assert_eq!(search.first(|vm, _| vm.get(0) == w(42)), Ok(None));
+    }
+
+    #[test]
+    fn test_outputs_and_threads() {
+        let search = Search::new(Vm::from(SUM))
+            .vary(1, (0..=9).map(w))
+            .vary(2, (0..=9).map(w));
+        let condition = |_: &Vm, outputs: &[DefaultWord]| outputs == [w(9)];
+
+        let sequential = search.all(condition).unwrap();
+        assert_eq!(sequential.len(), 10);
+        assert_eq!(sequential[9], vec![w(9), w(0)]);
+
+        let parallel = search.clone().threads(4);
+        assert_eq!(parallel.all(condition), Ok(sequential));
+        for _ in 0..20 {
+            assert_eq!(parallel.first(condition), Ok(Some(vec![w(0), w(9)])));
+        }
+    }
+
+    #[test]
+    fn test_budget() {
+        // Address 2 holds the jump target: 0 loops forever, 4 halts.
+        let search = Search::new(Vm::from("1105,1,0,0,99"))
+            .vary(2, vec![w(0), w(4)])
+            .budget(Budget::new().steps(100));
+
+        assert_eq!(search.all(|_, _| true), Ok(vec![vec![w(4)]]));
+    }
+
+    #[test]
+    fn test_too_many() {
+        let mut search = Search::new(Vm::from(SUM));
+        for address in 0..8 {
+            search = search.vary(address, vec![w(0); 1 << 8]);
+        }
+        assert_eq!(search.len(), None);
+        assert!(!search.is_empty());
+        assert_eq!(search.first(|_, _| true), Err(TooManyAssignments));
+
+        assert_eq!(search.vary(9, Vec::new()).len(), Some(0));
+    }
+}
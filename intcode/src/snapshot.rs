@@ -0,0 +1,243 @@
+use crate::memory::Memory;
+use crate::vm::{Overflow, Vm};
+use crate::word::{decimal, Word};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+pub const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Format(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot i/o failed: {}", e),
+            SnapshotError::Format(e) => write!(f, "malformed snapshot: {}", e),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported snapshot version {} (expected {})",
+                version, VERSION
+            ),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(e: serde_json::Error) -> Self {
+        SnapshotError::Format(e)
+    }
+}
+
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "W: Word")]
+struct Snapshot<W> {
+    version: u32,
+    pointer: usize,
+    #[serde(with = "decimal")]
+    relative_base: W,
+    steps: u64,
+    #[serde(with = "decimal::vec")]
+    input: Vec<W>,
+    #[serde(with = "decimal::map")]
+    memory: BTreeMap<usize, W>,
+    /// Missing from snapshots saved before it was recorded, which all used the default.
+    #[serde(default)]
+    overflow: Overflow,
+}
+
+impl<W: Word, M: Memory<W>> Vm<W, M> {
+    /// Writes the VM's memory, registers, pending input and overflow policy. Trace records are
+    /// not included.
+    pub fn save<T: Write>(&self, writer: T) -> Result<(), SnapshotError> {
+        let snapshot = Snapshot {
+            version: VERSION,
+            pointer: self.pointer,
+            relative_base: self.relative_base.clone(),
+            steps: self.steps,
+            input: self.input.iter().cloned().collect(),
+            memory: self.memory.cells().into_iter().collect(),
+            overflow: self.overflow(),
+        };
+
+        serde_json::to_writer(writer, &snapshot)?;
+        Ok(())
+    }
+
+    pub fn load<R: Read>(mut reader: R) -> Result<Self, SnapshotError> {
+        let mut raw = Vec::new();
+        reader.read_to_end(&mut raw)?;
+
+        let header: Header = serde_json::from_slice(&raw)?;
+        if header.version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header.version));
+        }
+
+        let snapshot: Snapshot<W> = serde_json::from_slice(&raw)?;
+
+        let mut memory = M::from_program(Vec::new());
+        for (address, value) in snapshot.memory {
+            memory.set(address, value);
+        }
+
+        let mut vm = Vm::new(memory);
+        vm.pointer = snapshot.pointer;
+        vm.relative_base = snapshot.relative_base;
+        vm.steps = snapshot.steps;
+        vm.input = snapshot.input.into_iter().collect();
+        vm.set_overflow(snapshot.overflow);
+
+        Ok(vm)
+    }
+
+    pub fn save_file<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.save(&mut writer)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Vm::load(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::PagedMemory;
+    use crate::vm::{Overflow, State, Vm};
+    use crate::word::{w, DefaultWord};
+
+    #[test]
+    fn test_round_trip() {
+        let input = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let mut vm = Vm::from(input);
+        vm.add_input(w(42));
+
+        let mut output = Vec::new();
+        for _ in 0..5 {
+            if let State::Output(out) = vm.execute().unwrap() {
+                output.push(out);
+            }
+        }
+
+        let mut snapshot = Vec::new();
+        vm.save(&mut snapshot).unwrap();
+        let mut restored: Vm = Vm::load(snapshot.as_slice()).unwrap();
+
+        assert_eq!(restored.pointer(), vm.pointer());
+        assert_eq!(restored.relative_base(), vm.relative_base());
+        assert_eq!(restored.steps(), vm.steps());
+        assert_eq!(restored.input(), vm.input());
+
+        while let State::Output(out) = restored.execute().unwrap() {
+            output.push(out);
+        }
+
+        let output = output
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_fork() {
+        let mut vm = Vm::from("3,9,4,9,3,9,4,9,99");
+        vm.add_input(w(1));
+        assert_eq!(vm.execute(), Ok(State::Output(w(1))));
+
+        let mut snapshot = Vec::new();
+        vm.save(&mut snapshot).unwrap();
+
+        for value in 2..5 {
+            let mut fork: Vm = Vm::load(snapshot.as_slice()).unwrap();
+            fork.add_input(w(value));
+            assert_eq!(fork.execute(), Ok(State::Output(w(value))));
+        }
+    }
+
+    #[test]
+    fn test_wide_values() {
+        let mut vm = Vm::from("104,0,99");
+        vm.set(1, w(-170141183460469231731687303715884105728));
+
+        let mut snapshot = Vec::new();
+        vm.save(&mut snapshot).unwrap();
+        let mut restored: Vm = Vm::load(snapshot.as_slice()).unwrap();
+
+        assert_eq!(
+            restored.execute(),
+            Ok(State::Output(w(-170141183460469231731687303715884105728)))
+        );
+    }
+
+    #[test]
+    fn test_decimal_words() {
+        let mut vm = Vm::from("104,0,99");
+        vm.set(1, w(-170141183460469231731687303715884105728));
+        vm.add_input(w(7));
+
+        let mut snapshot = Vec::new();
+        vm.save(&mut snapshot).unwrap();
+
+        assert_eq!(
+            String::from_utf8(snapshot).unwrap(),
+            r#"{"version":1,"pointer":0,"relative_base":0,"steps":0,"input":[7],"memory":{"0":104,"1":"-170141183460469231731687303715884105728","2":99},"overflow":"trap"}"#
+        );
+    }
+
+    #[test]
+    fn test_overflow_policy() {
+        for overflow in [Overflow::Wrap, Overflow::Saturate, Overflow::Trap] {
+            let mut vm = Vm::<i64>::parse("1101,9223372036854775807,1,7,4,7,99");
+            vm.set_overflow(overflow);
+
+            let mut snapshot = Vec::new();
+            vm.save(&mut snapshot).unwrap();
+            let mut restored: Vm<i64> = Vm::load(snapshot.as_slice()).unwrap();
+
+            assert_eq!(restored.overflow(), overflow);
+            assert_eq!(restored.execute(), vm.execute());
+        }
+
+        let old =
+            r#"{"version":1,"pointer":0,"relative_base":0,"steps":0,"input":[],"memory":{"0":99}}"#;
+        let vm: Vm = Vm::load(old.as_bytes()).unwrap();
+        assert_eq!(vm.overflow(), Overflow::Trap);
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let snapshot = r#"{"version":0,"registers":[]}"#;
+
+        match Vm::<DefaultWord, PagedMemory>::load(snapshot.as_bytes()) {
+            Err(super::SnapshotError::UnsupportedVersion(0)) => {}
+            other => panic!("unexpected result {:?}", other.map(|vm| vm.pointer())),
+        }
+    }
+}
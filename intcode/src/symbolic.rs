@@ -0,0 +1,694 @@
+use crate::budget::Budget;
+use crate::memory::Memory;
+use crate::vm::{Opcode, ParameterMode, State, Vm};
+use crate::word::Word;
+use crate::VmError;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Variable {
+    /// The initial value of a memory cell.
+    Cell(usize),
+    /// A value read by `in`, numbered however the caller likes.
+    Input(usize),
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variable::Cell(address) => write!(f, "[{}]", address),
+            Variable::Input(n) => write!(f, "in{}", n),
+        }
+    }
+}
+
+/// `constant` plus the sum of each variable times its coefficient.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Linear {
+    pub constant: i128,
+    pub terms: BTreeMap<Variable, i128>,
+}
+
+impl Linear {
+    fn add(&self, other: &Linear) -> Option<Linear> {
+        let mut sum = Linear {
+            constant: self.constant.checked_add(other.constant)?,
+            terms: self.terms.clone(),
+        };
+        for (&variable, &coefficient) in &other.terms {
+            let total = sum
+                .terms
+                .get(&variable)
+                .copied()
+                .unwrap_or(0)
+                .checked_add(coefficient)?;
+            if total == 0 {
+                sum.terms.remove(&variable);
+            } else {
+                sum.terms.insert(variable, total);
+            }
+        }
+
+        Some(sum)
+    }
+
+    fn scale(&self, factor: i128) -> Option<Linear> {
+        if factor == 0 {
+            return Some(Linear::default());
+        }
+
+        let mut terms = BTreeMap::new();
+        for (&variable, &coefficient) in &self.terms {
+            terms.insert(variable, coefficient.checked_mul(factor)?);
+        }
+
+        Some(Linear {
+            constant: self.constant.checked_mul(factor)?,
+            terms,
+        })
+    }
+
+    /// Value of the expression once `variables` are known, or `None` if it overflows.
+    fn evaluate(&self, variables: &BTreeMap<Variable, i128>) -> Option<i128> {
+        self.terms
+            .iter()
+            .try_fold(self.constant, |total, (variable, &coefficient)| {
+                total.checked_add(coefficient.checked_mul(*variables.get(variable)?)?)
+            })
+    }
+}
+
+impl fmt::Display for Linear {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for (variable, &coefficient) in &self.terms {
+            let sign = if coefficient < 0 { "-" } else { "+" };
+            if first {
+                if coefficient < 0 {
+                    write!(f, "-")?;
+                }
+            } else {
+                write!(f, " {} ", sign)?;
+            }
+            if coefficient.abs() != 1 {
+                write!(f, "{}*", coefficient.abs())?;
+            }
+            write!(f, "{}", variable)?;
+            first = false;
+        }
+
+        if first {
+            write!(f, "{}", self.constant)
+        } else if self.constant < 0 {
+            write!(f, " - {}", -self.constant)
+        } else if self.constant > 0 {
+            write!(f, " + {}", self.constant)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A value computed from variables.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expr {
+    Linear(Linear),
+    Add(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    LessThan(Box<Expr>, Box<Expr>),
+    Equals(Box<Expr>, Box<Expr>),
+    /// Read through an address that depends on variables.
+    Unknown,
+}
+
+impl Expr {
+    pub fn constant(value: i128) -> Self {
+        Expr::Linear(Linear {
+            constant: value,
+            terms: BTreeMap::new(),
+        })
+    }
+
+    pub fn variable(variable: Variable) -> Self {
+        let mut terms = BTreeMap::new();
+        terms.insert(variable, 1);
+        Expr::Linear(Linear { constant: 0, terms })
+    }
+
+    pub fn as_constant(&self) -> Option<i128> {
+        match self {
+            Expr::Linear(linear) if linear.terms.is_empty() => Some(linear.constant),
+            _ => None,
+        }
+    }
+
+    pub fn as_linear(&self) -> Option<&Linear> {
+        match self {
+            Expr::Linear(linear) => Some(linear),
+            _ => None,
+        }
+    }
+
+    /// `None` if a constant part overflows.
+    fn add(&self, other: &Expr) -> Option<Expr> {
+        Some(match (self, other) {
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            (Expr::Linear(lhs), Expr::Linear(rhs)) => Expr::Linear(lhs.add(rhs)?),
+            _ => Expr::Add(Box::new(self.clone()), Box::new(other.clone())),
+        })
+    }
+
+    fn mul(&self, other: &Expr) -> Option<Expr> {
+        Some(match (self, other) {
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            (Expr::Linear(lhs), _) if lhs.terms.is_empty() => match other {
+                Expr::Linear(rhs) => Expr::Linear(rhs.scale(lhs.constant)?),
+                _ if lhs.constant == 0 => Expr::constant(0),
+                _ if lhs.constant == 1 => other.clone(),
+                _ => Expr::Mul(Box::new(self.clone()), Box::new(other.clone())),
+            },
+            (_, Expr::Linear(rhs)) if rhs.terms.is_empty() => other.mul(self)?,
+            _ => Expr::Mul(Box::new(self.clone()), Box::new(other.clone())),
+        })
+    }
+
+    fn compare(&self, other: &Expr, equals: bool) -> Expr {
+        match (self, other) {
+            (Expr::Unknown, _) | (_, Expr::Unknown) => Expr::Unknown,
+            _ => match (self.as_constant(), other.as_constant()) {
+                (Some(lhs), Some(rhs)) if equals => Expr::constant((lhs == rhs) as i128),
+                (Some(lhs), Some(rhs)) => Expr::constant((lhs < rhs) as i128),
+                _ if equals => Expr::Equals(Box::new(self.clone()), Box::new(other.clone())),
+                _ => Expr::LessThan(Box::new(self.clone()), Box::new(other.clone())),
+            },
+        }
+    }
+
+    /// Every assignment of values to the variables in `ranges` that makes this expression
+    /// equal `target`, listed in `ranges` order and sorted. All but one variable are
+    /// enumerated and the last is solved for, so only the expression has to be linear.
+    pub fn solve(
+        &self,
+        target: i128,
+        ranges: &[(Variable, RangeInclusive<i128>)],
+    ) -> Result<Vec<Vec<i128>>, SymbolicError> {
+        let linear = match self {
+            Expr::Linear(linear) => linear,
+            _ => return Err(SymbolicError::NonLinear(self.clone())),
+        };
+        if let Some(&variable) = linear
+            .terms
+            .keys()
+            .find(|variable| !ranges.iter().any(|(v, _)| v == *variable))
+        {
+            return Err(SymbolicError::Unbounded(variable));
+        }
+
+        // Solve for the variable with the widest range, so the fewest values are enumerated.
+        let pivot = ranges
+            .iter()
+            .enumerate()
+            .filter(|(_, (variable, _))| linear.terms.contains_key(variable))
+            .max_by_key(|(_, (_, range))| range.end().saturating_sub(*range.start()))
+            .map(|(i, _)| i);
+
+        let mut solutions = Vec::new();
+        let mut values = BTreeMap::new();
+        enumerate(
+            linear,
+            target,
+            ranges,
+            pivot,
+            0,
+            &mut values,
+            &mut solutions,
+        );
+        solutions.sort_unstable();
+        Ok(solutions)
+    }
+}
+
+fn enumerate(
+    linear: &Linear,
+    target: i128,
+    ranges: &[(Variable, RangeInclusive<i128>)],
+    pivot: Option<usize>,
+    index: usize,
+    values: &mut BTreeMap<Variable, i128>,
+    solutions: &mut Vec<Vec<i128>>,
+) {
+    if index < ranges.len() && Some(index) != pivot {
+        let (variable, range) = &ranges[index];
+        for value in range.clone() {
+            values.insert(*variable, value);
+            enumerate(linear, target, ranges, pivot, index + 1, values, solutions);
+        }
+        return;
+    }
+    if index < ranges.len() {
+        return enumerate(linear, target, ranges, pivot, index + 1, values, solutions);
+    }
+
+    if let Some(pivot) = pivot {
+        let (variable, range) = &ranges[pivot];
+        let coefficient = linear.terms[variable];
+        values.insert(*variable, 0);
+        let rest = match linear.evaluate(values) {
+            Some(rest) => rest,
+            None => return,
+        };
+        let remainder = match target.checked_sub(rest) {
+            Some(remainder) if remainder % coefficient == 0 => remainder,
+            _ => return,
+        };
+        let value = remainder / coefficient;
+        if !range.contains(&value) {
+            return;
+        }
+        values.insert(*variable, value);
+    } else if linear.evaluate(values) != Some(target) {
+        return;
+    }
+
+    solutions.push(
+        ranges
+            .iter()
+            .map(|(variable, _)| values[variable])
+            .collect(),
+    );
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Linear(linear) => write!(f, "{}", linear),
+            Expr::Add(lhs, rhs) => write!(f, "({} + {})", lhs, rhs),
+            Expr::Mul(lhs, rhs) => write!(f, "({} * {})", lhs, rhs),
+            Expr::LessThan(lhs, rhs) => write!(f, "({} < {})", lhs, rhs),
+            Expr::Equals(lhs, rhs) => write!(f, "({} == {})", lhs, rhs),
+            Expr::Unknown => write!(f, "?"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SymbolicError {
+    Vm(VmError<i128>),
+    /// A jump condition or target depends on variables, so the path isn't straight-line.
+    SymbolicBranch {
+        pointer: usize,
+    },
+    /// An opcode, write address or relative base adjustment depends on variables.
+    SymbolicAddress {
+        pointer: usize,
+    },
+    Overflow {
+        pointer: usize,
+    },
+    /// A cell of the VM being copied doesn't fit in an `i128`.
+    OutOfRange {
+        address: usize,
+    },
+    NonLinear(Expr),
+    /// A variable in the expression being solved was given no range.
+    Unbounded(Variable),
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::Vm(e) => write!(f, "{}", e),
+            SymbolicError::SymbolicBranch { pointer } => {
+                write!(f, "jump at {} depends on a variable", pointer)
+            }
+            SymbolicError::SymbolicAddress { pointer } => {
+                write!(f, "address used at {} depends on a variable", pointer)
+            }
+            SymbolicError::Overflow { pointer } => write!(f, "overflow at {}", pointer),
+            SymbolicError::OutOfRange { address } => {
+                write!(f, "value at {} doesn't fit in 128 bits", address)
+            }
+            SymbolicError::NonLinear(expr) => write!(f, "{} is not linear", expr),
+            SymbolicError::Unbounded(variable) => write!(f, "no range given for {}", variable),
+        }
+    }
+}
+
+impl Error for SymbolicError {}
+
+impl From<VmError<i128>> for SymbolicError {
+    fn from(e: VmError<i128>) -> Self {
+        SymbolicError::Vm(e)
+    }
+}
+
+/// Runs a program with some memory cells or inputs left as variables, building the
+/// expression for every value it computes. Only straight-line paths can be followed: jumps
+/// and addresses have to work out to constants.
+#[derive(Clone, Debug)]
+pub struct Executor {
+    memory: HashMap<usize, Expr>,
+    pointer: usize,
+    relative_base: i128,
+    input: VecDeque<Expr>,
+    steps: u64,
+}
+
+impl Executor {
+    /// Starts from a copy of `vm`'s memory, registers and pending input.
+    pub fn new<W: Word, M: Memory<W>>(vm: &Vm<W, M>) -> Result<Self, SymbolicError> {
+        let constant = |address: usize, value: &W| {
+            value
+                .to_i128()
+                .map(Expr::constant)
+                .ok_or(SymbolicError::OutOfRange { address })
+        };
+
+        let mut memory = HashMap::new();
+        for (address, value) in vm.memory.cells() {
+            memory.insert(address, constant(address, &value)?);
+        }
+
+        Ok(Executor {
+            memory,
+            pointer: vm.pointer,
+            relative_base: vm
+                .relative_base
+                .to_i128()
+                .ok_or(SymbolicError::OutOfRange {
+                    address: vm.pointer,
+                })?,
+            input: vm
+                .input
+                .iter()
+                .map(|value| constant(vm.pointer, value))
+                .collect::<Result<_, _>>()?,
+            steps: 0,
+        })
+    }
+
+    /// Replaces the value at `address` with the variable `Variable::Cell(address)`.
+    pub fn variable(&mut self, address: usize) {
+        self.set(address, Expr::variable(Variable::Cell(address)));
+    }
+
+    pub fn add_input(&mut self, input: Expr) {
+        self.input.push_back(input);
+    }
+
+    pub fn get(&self, address: usize) -> Expr {
+        self.memory
+            .get(&address)
+            .cloned()
+            .unwrap_or_else(|| Expr::constant(0))
+    }
+
+    pub fn set(&mut self, address: usize, value: Expr) {
+        self.memory.insert(address, value);
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn execute(&mut self) -> Result<State<Expr>, SymbolicError> {
+        self.execute_within(Budget::new())
+    }
+
+    /// Like `Vm::execute_within`, but with outputs as expressions.
+    pub fn execute_within(&mut self, budget: Budget) -> Result<State<Expr>, SymbolicError> {
+        let mut executed = 0;
+
+        loop {
+            if budget.exhausted(executed) {
+                return Ok(State::BudgetExhausted);
+            }
+            // Like `Vm`, running into a cell that was never written halts without a step.
+            if !self.memory.contains_key(&self.pointer) {
+                return Ok(State::Halted);
+            }
+            if let Some(state) = self.step()? {
+                if state != State::AwaitingInput {
+                    self.steps += 1;
+                }
+                return Ok(state);
+            }
+            self.steps += 1;
+            executed += 1;
+        }
+    }
+
+    fn step(&mut self) -> Result<Option<State<Expr>>, SymbolicError> {
+        let pointer = self.pointer;
+        let op = self
+            .get(pointer)
+            .as_constant()
+            .ok_or(SymbolicError::SymbolicAddress { pointer })?;
+        let opcode = Opcode::decode(&op, pointer)?;
+
+        let length = match opcode.operation {
+            1 | 2 => {
+                let lhs = self.read(1, &opcode)?;
+                let rhs = self.read(2, &opcode)?;
+                let result = if opcode.operation == 1 {
+                    lhs.add(&rhs)
+                } else {
+                    lhs.mul(&rhs)
+                };
+                let result = result.ok_or(SymbolicError::Overflow { pointer })?;
+                self.write(3, &opcode, result)?;
+                4
+            }
+            3 => {
+                let value = match self.input.pop_front() {
+                    Some(value) => value,
+                    None => return Ok(Some(State::AwaitingInput)),
+                };
+                self.write(1, &opcode, value)?;
+                2
+            }
+            4 => {
+                let value = self.read(1, &opcode)?;
+                self.pointer += 2;
+                return Ok(Some(State::Output(value)));
+            }
+            5 | 6 => {
+                let value = self.constant(1, &opcode, SymbolicError::SymbolicBranch { pointer })?;
+                let target =
+                    self.constant(2, &opcode, SymbolicError::SymbolicBranch { pointer })?;
+                if (value != 0) == (opcode.operation == 5) {
+                    self.pointer = address(target, pointer)?;
+                    return Ok(None);
+                }
+                3
+            }
+            7 | 8 => {
+                let lhs = self.read(1, &opcode)?;
+                let rhs = self.read(2, &opcode)?;
+                self.write(3, &opcode, lhs.compare(&rhs, opcode.operation == 8))?;
+                4
+            }
+            9 => {
+                let offset =
+                    self.constant(1, &opcode, SymbolicError::SymbolicAddress { pointer })?;
+                self.relative_base = self
+                    .relative_base
+                    .checked_add(offset)
+                    .ok_or(SymbolicError::Overflow { pointer })?;
+                2
+            }
+            99 => return Ok(Some(State::Halted)),
+            _ => {
+                return Err(VmError::UnknownOpcode {
+                    pointer,
+                    opcode: op,
+                }
+                .into())
+            }
+        };
+
+        self.pointer += length;
+        Ok(None)
+    }
+
+    /// The address the `parameter`th parameter refers to, or `None` if it depends on
+    /// variables.
+    fn address(&self, parameter: usize, opcode: &Opcode) -> Result<Option<usize>, SymbolicError> {
+        let cell = self.pointer + parameter;
+        let base = match opcode.get_mode(parameter - 1) {
+            ParameterMode::Immediate => return Ok(Some(cell)),
+            ParameterMode::Position => 0,
+            ParameterMode::Relative => self.relative_base,
+        };
+
+        match self.get(cell).as_constant() {
+            Some(raw) => match raw.checked_add(base) {
+                Some(target) => Ok(Some(address(target, self.pointer)?)),
+                None => Err(VmError::AddressOverflow {
+                    pointer: self.pointer,
+                    address: raw,
+                }
+                .into()),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn read(&self, parameter: usize, opcode: &Opcode) -> Result<Expr, SymbolicError> {
+        Ok(match self.address(parameter, opcode)? {
+            Some(address) => self.get(address),
+            None => Expr::Unknown,
+        })
+    }
+
+    fn constant(
+        &self,
+        parameter: usize,
+        opcode: &Opcode,
+        error: SymbolicError,
+    ) -> Result<i128, SymbolicError> {
+        self.read(parameter, opcode)?.as_constant().ok_or(error)
+    }
+
+    fn write(
+        &mut self,
+        parameter: usize,
+        opcode: &Opcode,
+        value: Expr,
+    ) -> Result<(), SymbolicError> {
+        if opcode.get_mode(parameter - 1) == ParameterMode::Immediate {
+            return Err(VmError::ImmediateWrite {
+                pointer: self.pointer,
+                opcode: self.get(self.pointer).as_constant().unwrap_or(0),
+            }
+            .into());
+        }
+
+        match self.address(parameter, opcode)? {
+            Some(address) => {
+                self.set(address, value);
+                Ok(())
+            }
+            None => Err(SymbolicError::SymbolicAddress {
+                pointer: self.pointer,
+            }),
+        }
+    }
+}
+
+fn address(target: i128, pointer: usize) -> Result<usize, SymbolicError> {
+    if target < 0 {
+        return Err(VmError::NegativeAddress {
+            pointer,
+            address: target,
+        }
+        .into());
+    }
+
+    usize::try_from(target).map_err(|_| {
+        VmError::AddressOverflow {
+            pointer,
+            address: target,
+        }
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PROGRAM: &str = "1,9,10,3,2,3,11,0,99,30,40,50";
+
+    #[test]
+    fn test_linear() {
+        let mut executor = Executor::new(&Vm::from(PROGRAM)).unwrap();
+        executor.variable(9);
+        executor.variable(10);
+
+        assert_eq!(executor.execute(), Ok(State::Halted));
+        let result = executor.get(0);
+        assert_eq!(result.to_string(), "50*[9] + 50*[10]");
+
+        let ranges = [(Variable::Cell(9), 0..=100), (Variable::Cell(10), 0..=100)];
+        let solutions = result.solve(3500, &ranges).unwrap();
+        assert_eq!(solutions.len(), 71);
+        assert_eq!(solutions[0], vec![0, 70]);
+        assert_eq!(solutions[70], vec![70, 0]);
+        assert!(result.solve(3501, &ranges).unwrap().is_empty());
+        assert_eq!(
+            result.solve(3500, &ranges[..1]),
+            Err(SymbolicError::Unbounded(Variable::Cell(10)))
+        );
+    }
+
+    #[test]
+    fn test_inputs() {
+        let mut executor = Executor::new(&Vm::from("3,0,1002,0,-3,0,1001,0,7,0,4,0,99")).unwrap();
+
+        assert_eq!(executor.execute(), Ok(State::AwaitingInput));
+        executor.add_input(Expr::variable(Variable::Input(0)));
+        let output = match executor.execute().unwrap() {
+            State::Output(output) => output,
+            state => panic!("unexpected {:?}", state),
+        };
+        assert_eq!(output.to_string(), "-3*in0 + 7");
+        assert_eq!(
+            output.solve(-8, &[(Variable::Input(0), -10..=10)]),
+            Ok(vec![vec![5]])
+        );
+        assert_eq!(executor.execute(), Ok(State::Halted));
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut executor = Executor::new(&Vm::from("2,5,5,0,99,3")).unwrap();
+        executor.variable(5);
+        executor.execute().unwrap();
+        assert_eq!(executor.get(0).to_string(), "([5] * [5])");
+        assert!(matches!(
+            executor.get(0).solve(9, &[(Variable::Cell(5), 0..=9)]),
+            Err(SymbolicError::NonLinear(_))
+        ));
+
+        let mut executor = Executor::new(&Vm::from("1,0,0,0,99")).unwrap();
+        executor.variable(1);
+        executor.execute().unwrap();
+        assert_eq!(executor.get(0), Expr::Unknown);
+
+        let mut executor = Executor::new(&Vm::from("1005,7,6,104,0,99,0")).unwrap();
+        executor.variable(7);
+        assert_eq!(
+            executor.execute(),
+            Err(SymbolicError::SymbolicBranch { pointer: 0 })
+        );
+    }
+
+    #[test]
+    fn test_stops_like_vm() {
+        let mut vm = Vm::<i128>::parse("42,0,0,0");
+        let mut executor = Executor::new(&vm).unwrap();
+        let error = VmError::UnknownOpcode {
+            pointer: 0,
+            opcode: 42,
+        };
+        assert_eq!(vm.execute(), Err(error.clone()));
+        assert_eq!(executor.execute(), Err(SymbolicError::Vm(error)));
+
+        // Runs off the end of the program into unwritten memory.
+        let mut vm = Vm::<i128>::parse("1101,1,2,5,1101");
+        let mut executor = Executor::new(&vm).unwrap();
+        assert_eq!(vm.execute(), Ok(State::Halted));
+        assert_eq!(executor.execute(), Ok(State::Halted));
+        assert_eq!(executor.get(0), Expr::constant(3));
+        assert_eq!(executor.steps(), vm.steps());
+    }
+}
@@ -0,0 +1,332 @@
+use crate::disasm;
+use crate::memory::Memory;
+use crate::vm::{ParameterMode, State, Vm};
+use crate::word::{decimal, DefaultWord, Word};
+use crate::VmError;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "W: Word")]
+pub struct TraceOperand<W = DefaultWord> {
+    pub mode: ParameterMode,
+    #[serde(with = "decimal")]
+    pub raw: W,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<usize>,
+    #[serde(with = "decimal")]
+    pub value: W,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "W: Word")]
+pub struct TraceWrite<W = DefaultWord> {
+    pub address: usize,
+    #[serde(with = "decimal")]
+    pub value: W,
+}
+
+/// One executed instruction. Operand values are those read before the instruction ran, so a
+/// write operand shows the value it overwrote. An instruction that failed is recorded with the
+/// error and without a step of its own.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "W: Word")]
+pub struct TraceRecord<W = DefaultWord> {
+    pub step: u64,
+    pub pointer: usize,
+    #[serde(with = "decimal")]
+    pub opcode: W,
+    pub mnemonic: String,
+    pub operands: Vec<TraceOperand<W>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub writes: Vec<TraceWrite<W>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "decimal::option"
+    )]
+    pub relative_base: Option<W>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "decimal::option"
+    )]
+    pub input: Option<W>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "decimal::option"
+    )]
+    pub output: Option<W>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl<W> TraceRecord<W> {
+    pub(crate) fn new(step: u64, pointer: usize, opcode: W, operation: usize) -> Self {
+        TraceRecord {
+            step,
+            pointer,
+            opcode,
+            mnemonic: disasm::mnemonic(operation).unwrap_or("???").to_string(),
+            operands: Vec::new(),
+            writes: Vec::new(),
+            relative_base: None,
+            input: None,
+            output: None,
+            error: None,
+        }
+    }
+}
+
+impl<W: Word> TraceRecord<W> {
+    /// An instruction that couldn't be decoded.
+    pub(crate) fn failed(step: u64, pointer: usize, opcode: W, error: &VmError<W>) -> Self {
+        let mut record = TraceRecord::new(step, pointer, opcode, usize::MAX);
+        record.error = Some(error.to_string());
+        record
+    }
+}
+
+#[derive(Debug)]
+pub enum TraceError<W = DefaultWord> {
+    Vm(VmError<W>),
+    Io(io::Error),
+}
+
+impl<W: Word> fmt::Display for TraceError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceError::Vm(e) => write!(f, "{}", e),
+            TraceError::Io(e) => write!(f, "failed to write trace: {}", e),
+        }
+    }
+}
+
+impl<W: Word> Error for TraceError<W> {}
+
+impl<W> From<VmError<W>> for TraceError<W> {
+    fn from(e: VmError<W>) -> Self {
+        TraceError::Vm(e)
+    }
+}
+
+impl<W> From<io::Error> for TraceError<W> {
+    fn from(e: io::Error) -> Self {
+        TraceError::Io(e)
+    }
+}
+
+/// Writes a VM's trace records as JSON lines.
+pub struct Tracer<W: Write> {
+    writer: W,
+}
+
+impl Tracer<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Tracer::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(writer: W) -> Self {
+        Tracer { writer }
+    }
+
+    /// Runs `vm` until it stops, writing a line for every instruction as it executes,
+    /// including one that fails. The output is flushed once, when the run stops.
+    pub fn execute<T: Word, M: Memory<T>>(
+        &mut self,
+        vm: &mut Vm<T, M>,
+    ) -> Result<State<T>, TraceError<T>> {
+        vm.enable_trace();
+        let result = loop {
+            let result = vm.step();
+            self.append(vm)?;
+            match result {
+                Ok(None) => {}
+                Ok(Some(state)) => break Ok(state),
+                Err(e) => break Err(e),
+            }
+        };
+        self.writer.flush()?;
+
+        Ok(result?)
+    }
+
+    /// Drains the records collected by `vm` into the output.
+    pub fn write<T: Word, M: Memory<T>>(&mut self, vm: &mut Vm<T, M>) -> io::Result<()> {
+        self.append(vm)?;
+        self.writer.flush()
+    }
+
+    fn append<T: Word, M: Memory<T>>(&mut self, vm: &mut Vm<T, M>) -> io::Result<()> {
+        for record in vm.take_trace() {
+            serde_json::to_writer(&mut self.writer, &record)?;
+            self.writer.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+pub fn read<R: BufRead, W: Word>(reader: R) -> io::Result<Vec<TraceRecord<W>>> {
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::word::w;
+
+    #[test]
+    fn test_records() {
+        let mut vm = Vm::from("3,12,109,4,22201,8,6,11,4,15,99");
+        vm.add_input(w(7));
+        vm.enable_trace();
+
+        assert_eq!(vm.execute(), Ok(State::Output(w(106))));
+        let trace = vm.take_trace();
+
+        assert_eq!(trace.len(), 4);
+        assert_eq!(trace[0].mnemonic, "in");
+        assert_eq!(trace[0].input, Some(w(7)));
+        assert_eq!(
+            trace[0].writes,
+            vec![TraceWrite {
+                address: 12,
+                value: w(7)
+            }]
+        );
+        assert_eq!(trace[1].relative_base, Some(w(4)));
+        assert_eq!(
+            trace[2].operands,
+            vec![
+                TraceOperand {
+                    mode: ParameterMode::Relative,
+                    raw: w(8),
+                    address: Some(12),
+                    value: w(7)
+                },
+                TraceOperand {
+                    mode: ParameterMode::Relative,
+                    raw: w(6),
+                    address: Some(10),
+                    value: w(99)
+                },
+                TraceOperand {
+                    mode: ParameterMode::Relative,
+                    raw: w(11),
+                    address: Some(15),
+                    value: w(0)
+                },
+            ]
+        );
+        assert_eq!(trace[3].output, Some(w(106)));
+        assert_eq!(trace[3].step, 3);
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let mut vm = Vm::from("1101,2,3,5,104,0,99");
+        let mut tracer = Tracer::new(Vec::new());
+
+        assert_eq!(tracer.execute(&mut vm).unwrap(), State::Output(w(5)));
+        assert_eq!(tracer.execute(&mut vm).unwrap(), State::Halted);
+
+        let output = String::from_utf8(tracer.into_inner()).unwrap();
+        assert_eq!(
+            output.lines().next().unwrap(),
+            r#"{"step":0,"pointer":0,"opcode":1101,"mnemonic":"add","operands":[{"mode":"immediate","raw":2,"value":2},{"mode":"immediate","raw":3,"value":3},{"mode":"position","raw":5,"address":5,"value":0}],"writes":[{"address":5,"value":5}]}"#
+        );
+
+        let records = read(output.as_bytes()).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1].output, Some(w(5)));
+        assert_eq!(records[2].mnemonic, "hlt");
+    }
+
+    #[test]
+    fn test_failures() {
+        let mut vm = Vm::from("1101,2,3,5,42");
+        let mut tracer = Tracer::new(Vec::new());
+
+        match tracer.execute(&mut vm) {
+            Err(TraceError::Vm(VmError::UnknownOpcode { pointer: 4, .. })) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let output = String::from_utf8(tracer.into_inner()).unwrap();
+        let records: Vec<TraceRecord> = read(output.as_bytes()).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].step, 1);
+        assert_eq!(records[1].mnemonic, "???");
+        assert_eq!(records[1].error.as_deref(), Some("unknown opcode 42 at 4"));
+
+        let mut vm = Vm::from("1,-1,0,0");
+        vm.enable_trace();
+        assert!(vm.execute().is_err());
+        let trace = vm.take_trace();
+        assert_eq!(trace.len(), 1);
+        assert_eq!(trace[0].mnemonic, "add");
+        assert!(trace[0].error.is_some());
+
+        let mut vm = Vm::from("1101,2,3,5,104,0,99");
+        let mut full = [0u8; 16];
+        let mut tracer = Tracer::new(&mut full[..]);
+        match tracer.execute(&mut vm) {
+            Err(TraceError::Io(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(vm.steps(), 1);
+    }
+
+    #[test]
+    fn test_flushes_once() {
+        #[derive(Default)]
+        struct Counting {
+            lines: usize,
+            flushes: usize,
+        }
+
+        impl Write for Counting {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.lines += buf.iter().filter(|&&b| b == b'\n').count();
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.flushes += 1;
+                Ok(())
+            }
+        }
+
+        let mut vm = Vm::from("1101,2,3,5,1101,2,3,5,1101,2,3,5,99");
+        let mut tracer = Tracer::new(Counting::default());
+        assert_eq!(tracer.execute(&mut vm).unwrap(), State::Halted);
+
+        let counting = tracer.into_inner();
+        assert_eq!(counting.lines, 4);
+        assert_eq!(counting.flushes, 1);
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        let mut vm = Vm::from("104,1,99");
+        vm.execute().unwrap();
+
+        assert!(vm.take_trace().is_empty());
+    }
+}
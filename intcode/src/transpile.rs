@@ -0,0 +1,500 @@
+use crate::disasm::{self, Line, Operand};
+use crate::memory::{Memory, PagedMemory};
+use crate::network::Process;
+use crate::vm::{State, Vm};
+use crate::word::Word;
+use crate::VmError;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{self, Write};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TranspileError {
+    pub address: usize,
+}
+
+impl fmt::Display for TranspileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the value at {:04} does not fit in an i128",
+            self.address
+        )
+    }
+}
+
+impl Error for TranspileError {}
+
+/// The instruction at `pointer` can't run natively: its opcode has changed since the program
+/// was translated, or running it would fail.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Unsupported {
+    pub pointer: usize,
+}
+
+/// A program translated by `transpile`.
+pub trait Native {
+    fn add_input(&mut self, value: i128);
+
+    /// Runs one instruction, returning the state if it stopped the run, or leaves the pointer
+    /// where it is if it can't run the instruction.
+    fn step(&mut self) -> Result<Option<State<i128>>, Unsupported>;
+
+    fn execute(&mut self) -> Result<State<i128>, Unsupported> {
+        loop {
+            if let Some(state) = self.step()? {
+                return Ok(state);
+            }
+        }
+    }
+
+    /// Every written cell, in address order.
+    fn cells(&self) -> Vec<(usize, i128)>;
+
+    fn pointer(&self) -> usize;
+
+    fn relative_base(&self) -> i128;
+
+    fn steps(&self) -> u64;
+
+    fn input(&self) -> &VecDeque<i128>;
+}
+
+/// Runs a translated program natively until it meets something only the interpreter can
+/// run, then carries on in a `Vm` from the same state. Both trap on overflow, so the `Vm` uses
+/// the default `Overflow::Trap` policy and there is no way to pick another.
+#[derive(Clone, Debug)]
+pub enum Hybrid<P> {
+    Native(P),
+    Interpreted(Box<Vm<i128>>),
+}
+
+impl<P: Native> Hybrid<P> {
+    pub fn new(program: P) -> Self {
+        Hybrid::Native(program)
+    }
+
+    pub fn add_input(&mut self, value: i128) {
+        match self {
+            Hybrid::Native(program) => program.add_input(value),
+            Hybrid::Interpreted(vm) => vm.add_input(value),
+        }
+    }
+
+    pub fn is_native(&self) -> bool {
+        matches!(self, Hybrid::Native(_))
+    }
+
+    pub fn step(&mut self) -> Result<Option<State<i128>>, VmError<i128>> {
+        if let Hybrid::Native(program) = self {
+            match program.step() {
+                Ok(state) => return Ok(state),
+                Err(_) => *self = Hybrid::Interpreted(Box::new(interpreter(program))),
+            }
+        }
+
+        match self {
+            Hybrid::Interpreted(vm) => vm.step(),
+            Hybrid::Native(_) => unreachable!(),
+        }
+    }
+
+    pub fn execute(&mut self) -> Result<State<i128>, VmError<i128>> {
+        if let Hybrid::Native(program) = self {
+            match program.execute() {
+                Ok(state) => return Ok(state),
+                Err(_) => *self = Hybrid::Interpreted(Box::new(interpreter(program))),
+            }
+        }
+
+        match self {
+            Hybrid::Interpreted(vm) => vm.execute(),
+            Hybrid::Native(_) => unreachable!(),
+        }
+    }
+}
+
+impl<P: Native> Process<i128> for Hybrid<P> {
+    fn step(&mut self) -> Result<Option<State<i128>>, VmError<i128>> {
+        Hybrid::step(self)
+    }
+
+    fn add_input(&mut self, value: i128) {
+        Hybrid::add_input(self, value)
+    }
+}
+
+fn interpreter<P: Native>(program: &P) -> Vm<i128> {
+    let mut memory = PagedMemory::from_program(Vec::new());
+    for (address, value) in program.cells() {
+        memory.set(address, value);
+    }
+
+    let mut vm = Vm::new(memory);
+    vm.pointer = program.pointer();
+    vm.relative_base = program.relative_base();
+    vm.steps = program.steps();
+    vm.input = program.input().clone();
+    vm
+}
+
+/// Translates `program` into Rust source for a `Program` type implementing `Native`, to be
+/// `include!`d into a module of a crate that depends on this one.
+///
+/// Every cell that decodes as an instruction becomes a match arm that only runs while the cell
+/// still holds the same opcode, so operands may change freely but rewritten code, like anything
+/// else the generated code doesn't handle, is left to `Hybrid` to interpret.
+pub fn transpile<W: Word>(program: &[W]) -> Result<String, TranspileError> {
+    let cells = program
+        .iter()
+        .enumerate()
+        .map(|(address, value)| value.to_i128().ok_or(TranspileError { address }))
+        .collect::<Result<Vec<i128>, _>>()?;
+
+    let mut arms = String::new();
+    for (address, opcode) in cells.iter().enumerate() {
+        if let Some(Line::Instruction {
+            mnemonic, operands, ..
+        }) = disasm::decode(program, address)
+        {
+            if let Some(body) = body(address, mnemonic, &operands) {
+                writeln!(arms, "            ({}, {}) => {{", address, opcode).unwrap();
+                for line in body {
+                    writeln!(arms, "                {}", line).unwrap();
+                }
+                arms.push_str("            }\n");
+            }
+        }
+    }
+
+    let cells: Vec<String> = cells.iter().map(i128::to_string).collect();
+    Ok(TEMPLATE
+        .replace("{len}", &program.len().to_string())
+        .replace("{cells}", &cells.join(", "))
+        .replace("{arms}", &arms))
+}
+
+/// The statements for one instruction, or `None` if it always fails.
+fn body<W: Word>(address: usize, mnemonic: &str, operands: &[Operand<W>]) -> Option<Vec<String>> {
+    let read = |parameter: usize| {
+        let cell = address + 1 + parameter;
+        match operands[parameter] {
+            Operand::Position(_) => format!("self.load(self.address({})?)", cell),
+            Operand::Immediate(_) => format!("self.load({})", cell),
+            Operand::Relative(_) => format!("self.load(self.relative({})?)", cell),
+        }
+    };
+    let destination = |parameter: usize| {
+        let cell = address + 1 + parameter;
+        match operands[parameter] {
+            Operand::Position(_) => Some(format!("self.address({})?", cell)),
+            Operand::Immediate(_) => None,
+            Operand::Relative(_) => Some(format!("self.relative({})?", cell)),
+        }
+    };
+    let next = address + 1 + operands.len();
+
+    let body = match mnemonic {
+        "add" | "mul" | "lt" | "eq" => {
+            let value = match mnemonic {
+                "add" => format!("{}.checked_add({})?", read(0), read(1)),
+                "mul" => format!("{}.checked_mul({})?", read(0), read(1)),
+                "lt" => format!("({} < {}) as i128", read(0), read(1)),
+                _ => format!("({} == {}) as i128", read(0), read(1)),
+            };
+            vec![
+                format!("let value = {};", value),
+                format!("self.store({}, value)?;", destination(2)?),
+                format!("self.pointer = {};", next),
+            ]
+        }
+        "in" => vec![
+            "let value = match self.input.front() {".to_string(),
+            "    Some(&value) => value,".to_string(),
+            "    None => return Some(Some(State::AwaitingInput)),".to_string(),
+            "};".to_string(),
+            format!("self.store({}, value)?;", destination(0)?),
+            "self.input.pop_front();".to_string(),
+            format!("self.pointer = {};", next),
+        ],
+        "out" => vec![
+            format!("let value = {};", read(0)),
+            format!("self.pointer = {};", next),
+            "self.steps += 1;".to_string(),
+            "return Some(Some(State::Output(value)));".to_string(),
+        ],
+        "jnz" | "jz" => vec![
+            format!("let value = {};", read(0)),
+            format!("let target = {};", read(1)),
+            format!(
+                "self.pointer = if value {} 0 {{",
+                if mnemonic == "jnz" { "!=" } else { "==" }
+            ),
+            "    usize::try_from(target).ok()?".to_string(),
+            "} else {".to_string(),
+            format!("    {}", next),
+            "};".to_string(),
+        ],
+        "arb" => vec![
+            format!(
+                "self.relative_base = self.relative_base.checked_add({})?;",
+                read(0)
+            ),
+            format!("self.pointer = {};", next),
+        ],
+        _ => vec![
+            "self.steps += 1;".to_string(),
+            "return Some(Some(State::Halted));".to_string(),
+        ],
+    };
+
+    Some(body)
+}
+
+const TEMPLATE: &str = r#"// Generated by intcode::transpile.
+
+use intcode::transpile::{Native, Unsupported};
+use intcode::State;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+static PROGRAM: [i128; {len}] = [{cells}];
+
+/// Writes further out are left to the interpreter's sparse memory.
+const MAX_MEMORY: usize = 1 << 24;
+
+#[derive(Clone, Debug)]
+pub struct Program {
+    memory: Vec<i128>,
+    written: Vec<bool>,
+    pointer: usize,
+    relative_base: i128,
+    steps: u64,
+    input: VecDeque<i128>,
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Program {
+            memory: PROGRAM.to_vec(),
+            written: vec![true; {len}],
+            pointer: 0,
+            relative_base: 0,
+            steps: 0,
+            input: VecDeque::new(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Program {
+    pub fn get(&self, address: usize) -> i128 {
+        self.load(address)
+    }
+
+    pub fn set(&mut self, address: usize, value: i128) {
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, 0);
+            self.written.resize(address + 1, false);
+        }
+        self.memory[address] = value;
+        self.written[address] = true;
+    }
+
+    fn load(&self, address: usize) -> i128 {
+        self.memory.get(address).copied().unwrap_or(0)
+    }
+
+    fn address(&self, cell: usize) -> Option<usize> {
+        usize::try_from(self.load(cell)).ok()
+    }
+
+    fn relative(&self, cell: usize) -> Option<usize> {
+        usize::try_from(self.relative_base.checked_add(self.load(cell))?).ok()
+    }
+
+    fn store(&mut self, address: usize, value: i128) -> Option<()> {
+        if address >= MAX_MEMORY {
+            return None;
+        }
+        self.set(address, value);
+        Some(())
+    }
+
+    /// Runs the instruction at `pointer`, or returns `None` if only the interpreter can.
+    fn run(&mut self, pointer: usize) -> Option<Option<State<i128>>> {
+        match (pointer, self.load(pointer)) {
+{arms}            _ => return None,
+        }
+
+        self.steps += 1;
+        Some(None)
+    }
+}
+
+impl Native for Program {
+    fn add_input(&mut self, value: i128) {
+        self.input.push_back(value);
+    }
+
+    fn step(&mut self) -> Result<Option<State<i128>>, Unsupported> {
+        let pointer = self.pointer;
+        self.run(pointer).ok_or(Unsupported { pointer })
+    }
+
+    fn execute(&mut self) -> Result<State<i128>, Unsupported> {
+        loop {
+            let pointer = self.pointer;
+            match self.run(pointer) {
+                Some(Some(state)) => return Ok(state),
+                Some(None) => {}
+                None => return Err(Unsupported { pointer }),
+            }
+        }
+    }
+
+    fn cells(&self) -> Vec<(usize, i128)> {
+        (0..self.memory.len())
+            .filter(|&address| self.written[address])
+            .map(|address| (address, self.memory[address]))
+            .collect()
+    }
+
+    fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    fn relative_base(&self) -> i128 {
+        self.relative_base
+    }
+
+    fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    fn input(&self) -> &VecDeque<i128> {
+        &self.input
+    }
+}
+"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::network::Network;
+    use crate::vm::parse;
+    use crate::word::DefaultWord;
+
+    // Outputs its input plus 5.
+    const PROGRAM: &str = "3,9,1001,9,5,9,4,9,99,0";
+
+    /// Stands in for generated code that can't run anything.
+    struct Stub {
+        memory: Vec<i128>,
+        input: VecDeque<i128>,
+    }
+
+    impl Native for Stub {
+        fn add_input(&mut self, value: i128) {
+            self.input.push_back(value);
+        }
+
+        fn step(&mut self) -> Result<Option<State<i128>>, Unsupported> {
+            Err(Unsupported { pointer: 0 })
+        }
+
+        fn cells(&self) -> Vec<(usize, i128)> {
+            self.memory.iter().copied().enumerate().collect()
+        }
+
+        fn pointer(&self) -> usize {
+            0
+        }
+
+        fn relative_base(&self) -> i128 {
+            0
+        }
+
+        fn steps(&self) -> u64 {
+            0
+        }
+
+        fn input(&self) -> &VecDeque<i128> {
+            &self.input
+        }
+    }
+
+    #[test]
+    fn test_arms() {
+        let source = transpile(&parse::<DefaultWord>(PROGRAM)).unwrap();
+
+        assert!(source.contains("static PROGRAM: [i128; 10] = [3, 9, 1001, 9, 5, 9, 4, 9, 99, 0];"));
+        assert!(source.contains(
+            "            (2, 1001) => {
+                let value = self.load(self.address(3)?).checked_add(self.load(4))?;
+                self.store(self.address(5)?, value)?;
+                self.pointer = 6;
+            }
+"
+        ));
+        assert!(source.contains(
+            "            (6, 4) => {
+                let value = self.load(self.address(7)?);
+                self.pointer = 8;
+                self.steps += 1;
+                return Some(Some(State::Output(value)));
+            }
+"
+        ));
+        assert!(source.contains("            (8, 99) => {\n"));
+        assert!(!source.contains("{arms}"));
+    }
+
+    #[test]
+    fn test_jumps() {
+        let source = transpile(&parse::<DefaultWord>("1206,-1,0,99")).unwrap();
+
+        assert!(source.contains(
+            "            (0, 1206) => {
+                let value = self.load(self.relative(1)?);
+                let target = self.load(2);
+                self.pointer = if value == 0 {
+                    usize::try_from(target).ok()?
+                } else {
+                    3
+                };
+            }
+"
+        ));
+    }
+
+    #[test]
+    fn test_hybrid() {
+        let mut hybrid = Hybrid::new(Stub {
+            memory: parse(PROGRAM),
+            input: VecDeque::new(),
+        });
+        hybrid.add_input(37);
+        assert!(hybrid.is_native());
+
+        assert_eq!(hybrid.execute(), Ok(State::Output(42)));
+        assert!(!hybrid.is_native());
+        assert_eq!(hybrid.execute(), Ok(State::Halted));
+    }
+
+    #[test]
+    fn test_network() {
+        let stub = || {
+            Hybrid::new(Stub {
+                memory: parse(PROGRAM),
+                input: VecDeque::new(),
+            })
+        };
+        let mut network = Network::pipeline(vec![stub(), stub()]);
+        network.add_input(0, 32);
+
+        assert_eq!(network.run(), Ok(vec![State::Halted; 2]));
+        assert_eq!(network.outputs(1), &[42]);
+        assert!(!network.vm(1).is_native());
+    }
+}
@@ -0,0 +1,903 @@
+use crate::error::VmError;
+use crate::memory::{Memory, PagedMemory};
+use crate::profile::Profile;
+use crate::replay::Event;
+use crate::trace::{TraceOperand, TraceRecord, TraceWrite};
+use crate::watch::Watches;
+use crate::word::{DefaultWord, Word};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+const DECODE_CACHE_LIMIT: usize = 1 << 16;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum State<W = DefaultWord> {
+    Output(W),
+    AwaitingInput,
+    Halted,
+    BudgetExhausted,
+}
+
+/// What `add`, `mul` and `arb` do when the result doesn't fit in the word type.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    Wrap,
+    Saturate,
+    /// Stop with `VmError::Overflow`.
+    #[default]
+    Trap,
+}
+
+#[derive(Clone, Debug)]
+pub struct Vm<W: Word = DefaultWord, M: Memory<W> = PagedMemory<W>> {
+    pub(crate) memory: M,
+    pub(crate) pointer: usize,
+    pub(crate) input: VecDeque<W>,
+    pub(crate) relative_base: W,
+    pub(crate) steps: u64,
+    /// Where the `hlt` that stopped the run is, so stepping it again isn't counted.
+    halted: Option<usize>,
+    overflow: Overflow,
+    decoded: Vec<Option<Opcode>>,
+    trace: Option<Vec<TraceRecord<W>>>,
+    profile: Option<Profile>,
+    pub(crate) recording: Option<Vec<Event<W>>>,
+    pub(crate) watches: Option<Watches<W>>,
+}
+
+impl<W: Word, M: Memory<W>> Vm<W, M> {
+    pub fn new(memory: M) -> Self {
+        Vm {
+            memory,
+            pointer: 0,
+            input: VecDeque::new(),
+            relative_base: W::from(0),
+            steps: 0,
+            halted: None,
+            overflow: Overflow::default(),
+            decoded: Vec::new(),
+            trace: None,
+            profile: None,
+            recording: None,
+            watches: None,
+        }
+    }
+
+    /// Builds a VM from comma-separated source, for callers that pick their own word type.
+    pub fn parse(raw: &str) -> Self {
+        Vm::new(M::from_program(parse(raw)))
+    }
+
+    pub fn add_input(&mut self, input: W) {
+        self.input.push_back(input);
+    }
+
+    pub fn get(&self, address: usize) -> W {
+        self.memory.get(address)
+    }
+
+    /// Writes a cell from outside the program. Only writes made by instructions are counted
+    /// by the profile or reported by watchpoints.
+    pub fn set(&mut self, address: usize, value: W) {
+        self.invalidate(address);
+        self.memory.set(address, value);
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    pub fn relative_base(&self) -> &W {
+        &self.relative_base
+    }
+
+    pub fn input(&self) -> &VecDeque<W> {
+        &self.input
+    }
+
+    /// The number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Every cell up to the highest one written, so a single far-off write makes this huge.
+    /// Use `get` to look at part of memory.
+    pub fn dump(&self) -> Vec<W> {
+        let length = self.memory.cells().last().map_or(0, |&(max, _)| max + 1);
+        (0..length).map(|address| self.get(address)).collect()
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
+
+    /// Starts recording a `TraceRecord` for every instruction executed from now on.
+    pub fn enable_trace(&mut self) {
+        if self.trace.is_none() {
+            self.trace = Some(Vec::new());
+        }
+    }
+
+    pub fn disable_trace(&mut self) {
+        self.trace = None;
+    }
+
+    /// Drains the records collected since tracing was enabled or last drained.
+    pub fn take_trace(&mut self) -> Vec<TraceRecord<W>> {
+        match self.trace.as_mut() {
+            Some(trace) => trace.split_off(0),
+            None => Vec::new(),
+        }
+    }
+
+    /// Starts counting executions and memory accesses from now on.
+    pub fn enable_profile(&mut self) {
+        if self.profile.is_none() {
+            self.profile = Some(Profile::default());
+        }
+    }
+
+    pub fn disable_profile(&mut self) {
+        self.profile = None;
+    }
+
+    /// Returns the counts collected since profiling was enabled or last taken, and starts
+    /// afresh.
+    pub fn take_profile(&mut self) -> Profile {
+        match self.profile.as_mut() {
+            Some(profile) => std::mem::take(profile),
+            None => Profile::default(),
+        }
+    }
+
+    /// Starts logging every value read by `in` and written by `out`, for `replay`.
+    pub fn enable_recording(&mut self) {
+        if self.recording.is_none() {
+            self.recording = Some(Vec::new());
+        }
+    }
+
+    pub fn disable_recording(&mut self) {
+        self.recording = None;
+    }
+
+    /// Drains the events logged so far.
+    pub fn take_recording(&mut self) -> Vec<Event<W>> {
+        match self.recording.as_mut() {
+            Some(recording) => recording.split_off(0),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn execute(&mut self) -> Result<State<W>, VmError<W>> {
+        loop {
+            if let Some(state) = self.step()? {
+                return Ok(state);
+            }
+        }
+    }
+
+    /// Executes a single instruction, returning the state if it stopped the run.
+    pub fn step(&mut self) -> Result<Option<State<W>>, VmError<W>> {
+        let op = match self.memory.fetch(self.pointer) {
+            Some(op) => op,
+            None => return Ok(Some(State::Halted)),
+        };
+        let opcode = match self.decode(&op) {
+            Ok(opcode) => opcode,
+            Err(e) => {
+                if let Some(trace) = self.trace.as_mut() {
+                    trace.push(TraceRecord::failed(self.steps, self.pointer, op, &e));
+                }
+                return Err(e);
+            }
+        };
+        let pointer = self.pointer;
+        if opcode.operation == 99 && self.halted == Some(pointer) {
+            return Ok(Some(State::Halted));
+        }
+
+        let mut record = self
+            .trace
+            .as_ref()
+            .map(|_| TraceRecord::new(self.steps, self.pointer, op, opcode.operation));
+        if let Some(watches) = self.watches.as_mut() {
+            watches.execute(pointer, opcode.parameter_count().unwrap_or(0) + 1);
+        }
+        let consumed = match (&self.recording, opcode.operation) {
+            (Some(_), 3) => self.input.front().cloned(),
+            _ => None,
+        };
+
+        let result = self.instruction(&opcode, &mut record);
+
+        if let Ok(None) | Ok(Some(State::Output(_))) | Ok(Some(State::Halted)) = result {
+            if let Some(recording) = self.recording.as_mut() {
+                let step = self.steps;
+                match (&result, consumed) {
+                    (Ok(Some(State::Output(value))), _) => recording.push(Event::Output {
+                        step,
+                        value: value.clone(),
+                    }),
+                    (_, Some(value)) => recording.push(Event::Input { step, value }),
+                    _ => {}
+                }
+            }
+            self.steps += 1;
+            if let Ok(Some(State::Halted)) = result {
+                self.halted = Some(pointer);
+            }
+            if let Some(profile) = self.profile.as_mut() {
+                profile.instruction(pointer, opcode.operation);
+            }
+        }
+        if let (Some(trace), Some(mut record)) = (self.trace.as_mut(), record) {
+            match &result {
+                Ok(Some(State::AwaitingInput)) => {}
+                Ok(_) => trace.push(record),
+                Err(e) => {
+                    record.error = Some(e.to_string());
+                    trace.push(record);
+                }
+            }
+        }
+
+        result
+    }
+
+    fn instruction(
+        &mut self,
+        opcode: &Opcode,
+        record: &mut Option<TraceRecord<W>>,
+    ) -> Result<Option<State<W>>, VmError<W>> {
+        let length;
+        match opcode.operation {
+            1 => {
+                let lhs = self.read(1, opcode, record)?;
+                let rhs = self.read(2, opcode, record)?;
+
+                let sum = self.arithmetic(lhs, rhs, false)?;
+                self.write(3, opcode, sum, record)?;
+                length = 4;
+            }
+            2 => {
+                let lhs = self.read(1, opcode, record)?;
+                let rhs = self.read(2, opcode, record)?;
+
+                let product = self.arithmetic(lhs, rhs, true)?;
+                self.write(3, opcode, product, record)?;
+                length = 4;
+            }
+            3 => {
+                let arg = match self.input.front() {
+                    Some(arg) => arg.clone(),
+                    None => return Ok(Some(State::AwaitingInput)),
+                };
+
+                if let Some(record) = record {
+                    record.input = Some(arg.clone());
+                }
+                self.write(1, opcode, arg, record)?;
+                self.input.pop_front();
+                length = 2;
+            }
+            4 => {
+                let value = self.read(1, opcode, record)?;
+
+                if let Some(record) = record {
+                    record.output = Some(value.clone());
+                }
+                self.pointer += 2;
+                return Ok(Some(State::Output(value)));
+            }
+            5 => {
+                let value = self.read(1, opcode, record)?;
+                let destination = self.read(2, opcode, record)?;
+
+                if !value.is_zero() {
+                    length = 0;
+                    self.pointer = self.to_address(destination)?;
+                } else {
+                    length = 3;
+                }
+            }
+            6 => {
+                let value = self.read(1, opcode, record)?;
+                let destination = self.read(2, opcode, record)?;
+
+                if value.is_zero() {
+                    length = 0;
+                    self.pointer = self.to_address(destination)?;
+                } else {
+                    length = 3;
+                }
+            }
+            7 => {
+                let lhs = self.read(1, opcode, record)?;
+                let rhs = self.read(2, opcode, record)?;
+
+                self.write(3, opcode, W::from((lhs < rhs) as i32), record)?;
+                length = 4;
+            }
+            8 => {
+                let lhs = self.read(1, opcode, record)?;
+                let rhs = self.read(2, opcode, record)?;
+
+                self.write(3, opcode, W::from((lhs == rhs) as i32), record)?;
+                length = 4;
+            }
+            9 => {
+                let value = self.read(1, opcode, record)?;
+
+                self.relative_base = self.arithmetic(self.relative_base.clone(), value, false)?;
+                if let Some(record) = record {
+                    record.relative_base = Some(self.relative_base.clone());
+                }
+
+                length = 2;
+            }
+            99 => {
+                return Ok(Some(State::Halted));
+            }
+            _ => {
+                return Err(VmError::UnknownOpcode {
+                    pointer: self.pointer,
+                    opcode: self.get(self.pointer),
+                });
+            }
+        }
+
+        self.pointer += length;
+        Ok(None)
+    }
+
+    fn arithmetic(&self, lhs: W, rhs: W, multiply: bool) -> Result<W, VmError<W>> {
+        let result = match self.overflow {
+            Overflow::Wrap if multiply => Some(lhs.wrapping_mul(&rhs)),
+            Overflow::Wrap => Some(lhs.wrapping_add(&rhs)),
+            Overflow::Saturate if multiply => Some(lhs.saturating_mul(&rhs)),
+            Overflow::Saturate => Some(lhs.saturating_add(&rhs)),
+            Overflow::Trap if multiply => lhs.checked_mul(&rhs),
+            Overflow::Trap => lhs.checked_add(&rhs),
+        };
+
+        result.ok_or_else(|| VmError::Overflow {
+            pointer: self.pointer,
+            opcode: self.get(self.pointer),
+            lhs,
+            rhs,
+        })
+    }
+
+    fn decode(&mut self, op: &W) -> Result<Opcode, VmError<W>> {
+        if let Some(&Some(opcode)) = self.decoded.get(self.pointer) {
+            return Ok(opcode);
+        }
+
+        let opcode = Opcode::decode(op, self.pointer)?;
+        if self.pointer < DECODE_CACHE_LIMIT {
+            if self.pointer >= self.decoded.len() {
+                self.decoded.resize(self.pointer + 1, None);
+            }
+            self.decoded[self.pointer] = Some(opcode);
+        }
+
+        Ok(opcode)
+    }
+
+    /// Forgets the decoded instruction at `address` after it is overwritten.
+    fn invalidate(&mut self, address: usize) {
+        if let Some(decoded) = self.decoded.get_mut(address) {
+            *decoded = None;
+        }
+    }
+
+    /// Reads the value of the `parameter`th parameter (counting from 1) of the current
+    /// instruction.
+    fn read(
+        &mut self,
+        parameter: usize,
+        opcode: &Opcode,
+        record: &mut Option<TraceRecord<W>>,
+    ) -> Result<W, VmError<W>> {
+        let mode = opcode.get_mode(parameter - 1);
+        let address = self.resolve_address(self.pointer + parameter, mode)?;
+        let value = self.get(address);
+
+        if mode != ParameterMode::Immediate {
+            if let Some(profile) = self.profile.as_mut() {
+                profile.read(address);
+            }
+            if let Some(watches) = self.watches.as_mut() {
+                watches.read(self.steps, self.pointer, address, &value);
+            }
+        }
+        if let Some(record) = record {
+            record.operands.push(TraceOperand {
+                mode,
+                raw: self.get(self.pointer + parameter),
+                address: if mode == ParameterMode::Immediate {
+                    None
+                } else {
+                    Some(address)
+                },
+                value: value.clone(),
+            });
+        }
+
+        Ok(value)
+    }
+
+    /// Stores `value` at the address given by the `parameter`th parameter of the current
+    /// instruction.
+    fn write(
+        &mut self,
+        parameter: usize,
+        opcode: &Opcode,
+        value: W,
+        record: &mut Option<TraceRecord<W>>,
+    ) -> Result<(), VmError<W>> {
+        let mode = opcode.get_mode(parameter - 1);
+        if mode == ParameterMode::Immediate {
+            return Err(VmError::ImmediateWrite {
+                pointer: self.pointer,
+                opcode: self.get(self.pointer),
+            });
+        }
+
+        let address = self.resolve_address(self.pointer + parameter, mode)?;
+        if let Some(record) = record {
+            record.operands.push(TraceOperand {
+                mode,
+                raw: self.get(self.pointer + parameter),
+                address: Some(address),
+                value: self.get(address),
+            });
+            record.writes.push(TraceWrite {
+                address,
+                value: value.clone(),
+            });
+        }
+
+        if let Some(profile) = self.profile.as_mut() {
+            profile.write(address);
+        }
+        if let Some(watches) = self.watches.as_mut() {
+            watches.write(self.steps, self.pointer, address, &value);
+        }
+        self.invalidate(address);
+        self.memory.set(address, value);
+        Ok(())
+    }
+
+    fn resolve_address(&self, address: usize, mode: ParameterMode) -> Result<usize, VmError<W>> {
+        if mode == ParameterMode::Immediate {
+            Ok(address)
+        } else {
+            let source = self.get(address);
+            if mode == ParameterMode::Relative {
+                match source.checked_add(&self.relative_base) {
+                    Some(address) => self.to_address(address),
+                    None => Err(VmError::AddressOverflow {
+                        pointer: self.pointer,
+                        address: source,
+                    }),
+                }
+            } else {
+                self.to_address(source)
+            }
+        }
+    }
+
+    fn to_address(&self, address: W) -> Result<usize, VmError<W>> {
+        match address.to_address() {
+            Some(address) => Ok(address),
+            None if address.is_negative() => Err(VmError::NegativeAddress {
+                pointer: self.pointer,
+                address,
+            }),
+            None => Err(VmError::AddressOverflow {
+                pointer: self.pointer,
+                address,
+            }),
+        }
+    }
+}
+
+impl<T: Into<String>> From<T> for Vm {
+    fn from(raw: T) -> Self {
+        Vm::new(PagedMemory::from_program(parse(&raw.into())))
+    }
+}
+
+pub(crate) fn parameter_count(operation: usize) -> Option<usize> {
+    match operation {
+        1 | 2 | 7 | 8 => Some(3),
+        5 | 6 => Some(2),
+        3 | 4 | 9 => Some(1),
+        99 => Some(0),
+        _ => None,
+    }
+}
+
+pub fn parse<W: Word>(raw: &str) -> Vec<W> {
+    raw.split(',')
+        .map(|x| match x.trim().parse() {
+            Ok(int) => int,
+            Err(_) => panic!("invalid number {}", x),
+        })
+        .collect()
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParameterMode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Opcode {
+    pub(crate) operation: usize,
+    parameter_modes: [ParameterMode; 3],
+}
+
+impl Opcode {
+    pub(crate) fn decode<W: Word>(word: &W, pointer: usize) -> Result<Opcode, VmError<W>> {
+        let op = match word.to_i128() {
+            Some(op) if op >= 0 => op,
+            _ => {
+                return Err(VmError::UnknownOpcode {
+                    pointer,
+                    opcode: word.clone(),
+                })
+            }
+        };
+
+        let mut parameter_modes = [ParameterMode::Position; 3];
+        let mut modes = op / 100;
+        let mut parameter = 0;
+        while modes > 0 {
+            let mode = match modes % 10 {
+                0 => ParameterMode::Position,
+                1 => ParameterMode::Immediate,
+                2 => ParameterMode::Relative,
+                mode => {
+                    return Err(VmError::InvalidMode {
+                        pointer,
+                        opcode: word.clone(),
+                        mode,
+                    })
+                }
+            };
+
+            if let Some(slot) = parameter_modes.get_mut(parameter) {
+                *slot = mode;
+            }
+            modes /= 10;
+            parameter += 1;
+        }
+
+        Ok(Opcode {
+            operation: (op % 100) as usize,
+            parameter_modes,
+        })
+    }
+
+    pub(crate) fn parameter_count(&self) -> Option<usize> {
+        parameter_count(self.operation)
+    }
+
+    pub(crate) fn get_mode(&self, parameter: usize) -> ParameterMode {
+        *self
+            .parameter_modes
+            .get(parameter)
+            .unwrap_or(&ParameterMode::Position)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::word::w;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_big_multiply() {
+        let input = "1102,34915192,34915192,7,4,7,99,0";
+        let mut vm = Vm::from(input);
+
+        let output = vm.execute().unwrap();
+        assert_eq!(State::Output(w(1219070632396864)), output);
+    }
+
+    #[test]
+    fn test_big_output() {
+        let input = "104,1125899906842624,99";
+        let mut vm = Vm::from(input);
+
+        let output = vm.execute().unwrap();
+        assert_eq!(State::Output(w(1125899906842624)), output);
+    }
+
+    #[test]
+    fn test_immediate_multiplication() {
+        let input = "1002,4,3,4,33";
+        let mut vm = Vm::from(input);
+        vm.execute().unwrap();
+
+        assert_eq!(vm.get(4), w(99));
+    }
+
+    #[test]
+    fn test_immediate_addition() {
+        let input = "1101,100,-1,4,0";
+        let mut vm = Vm::from(input);
+        vm.execute().unwrap();
+
+        assert_eq!(vm.get(4), w(99));
+    }
+
+    #[test]
+    fn test_input_output() {
+        let input = "3,0,4,0,99";
+        let mut vm = Vm::from(input);
+        vm.add_input(w(1234));
+
+        let output = vm.execute().unwrap();
+
+        assert_eq!(State::Output(w(1234)), output);
+    }
+
+    #[test]
+    fn test_simple_addition() {
+        let input = "1,0,0,0,99";
+        let mut vm = Vm::from(input);
+        vm.add_input(w(1234));
+
+        vm.execute().unwrap();
+
+        assert_eq!(vm.get(0), w(2));
+    }
+
+    #[test]
+    fn test_simple_multiplication() {
+        let input = "2,3,0,3,99";
+        let mut vm = Vm::from(input);
+        vm.add_input(w(1234));
+
+        vm.execute().unwrap();
+
+        assert_eq!(vm.get(3), w(6));
+    }
+
+    #[test]
+    fn test_relative_address() {
+        let input = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+        let mut vm = Vm::from(input);
+        vm.add_input(w(1234));
+
+        let mut output = Vec::new();
+        while let State::Output(out) = vm.execute().unwrap() {
+            output.push(out);
+        }
+
+        let output = output
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_awaiting_input() {
+        let mut vm = Vm::from("3,0,4,0,3,0,4,0,99");
+
+        assert_eq!(vm.execute(), Ok(State::AwaitingInput));
+        assert_eq!(vm.execute(), Ok(State::AwaitingInput));
+
+        vm.add_input(w(12));
+        assert_eq!(vm.execute(), Ok(State::Output(w(12))));
+        assert_eq!(vm.execute(), Ok(State::AwaitingInput));
+
+        vm.add_input(w(34));
+        assert_eq!(vm.execute(), Ok(State::Output(w(34))));
+        assert_eq!(vm.execute(), Ok(State::Halted));
+    }
+
+    #[test]
+    fn test_halt_counted_once() {
+        let mut vm = Vm::from("104,1,99");
+        vm.enable_profile();
+
+        assert_eq!(vm.execute(), Ok(State::Output(w(1))));
+        assert_eq!(vm.execute(), Ok(State::Halted));
+        assert_eq!(vm.execute(), Ok(State::Halted));
+        assert_eq!(vm.steps(), 2);
+        assert_eq!(vm.take_profile().instructions[&2], 1);
+    }
+
+    #[test]
+    fn test_invalid_mode() {
+        let mut vm = Vm::from("1,0,0,0,301,0,0,0,99");
+
+        assert_eq!(
+            vm.execute(),
+            Err(VmError::InvalidMode {
+                pointer: 4,
+                opcode: w(301),
+                mode: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let mut vm = Vm::from("1,0,0,0,42");
+
+        assert_eq!(
+            vm.execute(),
+            Err(VmError::UnknownOpcode {
+                pointer: 4,
+                opcode: w(42)
+            })
+        );
+    }
+
+    #[test]
+    fn test_negative_address() {
+        let mut vm = Vm::from("109,-5,204,0,99");
+
+        assert_eq!(
+            vm.execute(),
+            Err(VmError::NegativeAddress {
+                pointer: 2,
+                address: w(-5)
+            })
+        );
+    }
+
+    #[test]
+    fn test_immediate_write() {
+        let mut vm = Vm::from("11101,1,1,0,99");
+
+        assert_eq!(
+            vm.execute(),
+            Err(VmError::ImmediateWrite {
+                pointer: 0,
+                opcode: w(11101)
+            })
+        );
+    }
+
+    #[test]
+    fn test_address_overflow() {
+        let mut vm = Vm::from("4,100000000000000000000000,99");
+
+        assert_eq!(
+            vm.execute(),
+            Err(VmError::AddressOverflow {
+                pointer: 0,
+                address: "100000000000000000000000".parse().unwrap()
+            })
+        );
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_beyond_i128() {
+        let mut vm = Vm::from("1102,170141183460469231731687303715884105727,4,7,4,7,99,0");
+
+        let output = vm.execute().unwrap();
+        assert_eq!(
+            State::Output("680564733841876926926749214863536422908".parse().unwrap()),
+            output
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let input = "1101,2147483647,1,5,99,0";
+
+        let mut vm: Vm<i32> = Vm::parse(input);
+        assert_eq!(
+            vm.execute(),
+            Err(VmError::Overflow {
+                pointer: 0,
+                opcode: 1101,
+                lhs: 2147483647,
+                rhs: 1
+            })
+        );
+
+        let mut vm: Vm<i32> = Vm::parse(input);
+        vm.set_overflow(Overflow::Wrap);
+        vm.execute().unwrap();
+        assert_eq!(vm.get(5), i32::MIN);
+
+        let mut vm: Vm<i32> = Vm::parse(input);
+        vm.set_overflow(Overflow::Saturate);
+        vm.execute().unwrap();
+        assert_eq!(vm.get(5), i32::MAX);
+
+        let mut vm: Vm<i64> = Vm::parse("1102,4294967296,4294967296,5,99,0");
+        assert_eq!(vm.execute().unwrap_err().pointer(), 0);
+    }
+
+    #[test]
+    fn test_narrow_words() {
+        let input = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+        let mut narrow: Vm<i32> = Vm::parse(input);
+        let mut output = Vec::new();
+        while let State::Output(out) = narrow.execute().unwrap() {
+            output.push(out.to_string());
+        }
+        assert_eq!(output.join(","), input);
+
+        let mut vm: Vm<i64> = Vm::parse("1,9,10,3,2,3,11,0,99,30,40,50");
+        vm.execute().unwrap();
+        assert_eq!(vm.get(0), 3500);
+    }
+
+    #[test]
+    fn test_hash_map_memory() {
+        let input = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        let mut vm: Vm<DefaultWord, HashMap<usize, DefaultWord>> = Vm::parse(input);
+
+        let mut output = Vec::new();
+        while let State::Output(out) = vm.execute().unwrap() {
+            output.push(out);
+        }
+
+        let output = output
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_decode() {
+        let opcode = Opcode::decode(&w(21107), 0).unwrap();
+
+        assert_eq!(opcode.operation, 7);
+        assert_eq!(opcode.get_mode(0), ParameterMode::Immediate);
+        assert_eq!(opcode.get_mode(1), ParameterMode::Immediate);
+        assert_eq!(opcode.get_mode(2), ParameterMode::Relative);
+        assert_eq!(opcode.get_mode(3), ParameterMode::Position);
+        assert_eq!(
+            Opcode::decode(&w(99), 0).unwrap().get_mode(0),
+            ParameterMode::Position
+        );
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        let source = "
+            start:  out #flag
+                    jnz [flag], #end
+                    add #1, #0, [flag]
+                    add #4, #0, [start]
+                    jz #0, #start
+            end:    hlt
+            flag:   data 0
+        ";
+        let program: Vec<DefaultWord> = crate::asm::assemble(source).unwrap();
+        let flag = w(program.len() as i128 - 1);
+        let mut vm = Vm::from(crate::asm::emit(&program));
+
+        assert_eq!(vm.execute(), Ok(State::Output(flag)));
+        assert_eq!(vm.execute(), Ok(State::Output(w(1))));
+        assert_eq!(vm.execute(), Ok(State::Halted));
+    }
+}
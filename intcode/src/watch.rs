@@ -0,0 +1,261 @@
+use crate::memory::Memory;
+use crate::vm::Vm;
+use crate::word::{DefaultWord, Word};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+/// Which accesses a watchpoint fires on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn reads(self) -> bool {
+        self != Access::Write
+    }
+
+    fn writes(self) -> bool {
+        self != Access::Read
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Access::Read => "reads",
+            Access::Write => "writes",
+            Access::ReadWrite => "reads and writes",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Trigger {
+    Read,
+    Write,
+    /// A write to an address that has already been executed as part of an instruction.
+    SelfModification,
+}
+
+/// A watched access, or a write into code. `value` is the value read or written.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Hit<W = DefaultWord> {
+    pub step: u64,
+    pub pointer: usize,
+    pub address: usize,
+    pub trigger: Trigger,
+    pub value: W,
+}
+
+impl<W: Word> fmt::Display for Hit<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.trigger {
+            Trigger::Read => write!(
+                f,
+                "{:04} read {} from {:04}",
+                self.pointer, self.value, self.address
+            ),
+            Trigger::Write => write!(
+                f,
+                "{:04} wrote {} to {:04}",
+                self.pointer, self.value, self.address
+            ),
+            Trigger::SelfModification => write!(
+                f,
+                "{:04} modified code at {:04} (now {})",
+                self.pointer, self.address, self.value
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Watches<W> {
+    watchpoints: BTreeMap<usize, Access>,
+    /// Addresses executed so far, when self-modification detection is on.
+    executed: Option<HashSet<usize>>,
+    hits: Vec<Hit<W>>,
+}
+
+impl<W: Word> Watches<W> {
+    pub(crate) fn execute(&mut self, pointer: usize, length: usize) {
+        if let Some(executed) = self.executed.as_mut() {
+            executed.extend(pointer..pointer + length);
+        }
+    }
+
+    pub(crate) fn read(&mut self, step: u64, pointer: usize, address: usize, value: &W) {
+        if self
+            .watchpoints
+            .get(&address)
+            .is_some_and(|access| access.reads())
+        {
+            self.hit(step, pointer, address, Trigger::Read, value);
+        }
+    }
+
+    pub(crate) fn write(&mut self, step: u64, pointer: usize, address: usize, value: &W) {
+        if self
+            .watchpoints
+            .get(&address)
+            .is_some_and(|access| access.writes())
+        {
+            self.hit(step, pointer, address, Trigger::Write, value);
+        }
+        if self
+            .executed
+            .as_ref()
+            .is_some_and(|executed| executed.contains(&address))
+        {
+            self.hit(step, pointer, address, Trigger::SelfModification, value);
+        }
+    }
+
+    fn hit(&mut self, step: u64, pointer: usize, address: usize, trigger: Trigger, value: &W) {
+        self.hits.push(Hit {
+            step,
+            pointer,
+            address,
+            trigger,
+            value: value.clone(),
+        });
+    }
+}
+
+impl<W: Word, M: Memory<W>> Vm<W, M> {
+    /// Reports accesses to `address` from now on, replacing any existing watchpoint there.
+    pub fn watch(&mut self, address: usize, access: Access) {
+        self.watches().watchpoints.insert(address, access);
+    }
+
+    /// Returns whether there was a watchpoint at `address`.
+    pub fn unwatch(&mut self, address: usize) -> bool {
+        self.watches().watchpoints.remove(&address).is_some()
+    }
+
+    pub fn watchpoints(&self) -> Vec<(usize, Access)> {
+        match self.watches.as_ref() {
+            Some(watches) => watches
+                .watchpoints
+                .iter()
+                .map(|(&address, &access)| (address, access))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Reports every write to an address that has been executed since detection was turned
+    /// on.
+    pub fn detect_self_modification(&mut self, enabled: bool) {
+        let watches = self.watches();
+        if !enabled {
+            watches.executed = None;
+        } else if watches.executed.is_none() {
+            watches.executed = Some(HashSet::new());
+        }
+    }
+
+    pub fn detects_self_modification(&self) -> bool {
+        self.watches
+            .as_ref()
+            .is_some_and(|watches| watches.executed.is_some())
+    }
+
+    /// Drains the hits reported so far.
+    pub fn take_hits(&mut self) -> Vec<Hit<W>> {
+        match self.watches.as_mut() {
+            Some(watches) => watches.hits.split_off(0),
+            None => Vec::new(),
+        }
+    }
+
+    fn watches(&mut self) -> &mut Watches<W> {
+        self.watches.get_or_insert_with(Watches::default)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vm::State;
+    use crate::word::w;
+
+    #[test]
+    fn test_watchpoints() {
+        let mut vm = Vm::from("1,9,10,3,2,3,11,0,99,30,40,50");
+        vm.watch(3, Access::ReadWrite);
+        vm.watch(10, Access::Write);
+        vm.watch(11, Access::Read);
+
+        assert_eq!(vm.execute(), Ok(State::Halted));
+        let hits = vm.take_hits();
+        assert_eq!(
+            hits.iter().map(Hit::to_string).collect::<Vec<String>>(),
+            vec![
+                "0000 wrote 70 to 0003",
+                "0004 read 70 from 0003",
+                "0004 read 50 from 0011",
+            ]
+        );
+        assert_eq!(hits[1].step, 1);
+        assert!(vm.take_hits().is_empty());
+
+        assert!(vm.unwatch(3));
+        assert!(!vm.unwatch(3));
+        assert_eq!(
+            vm.watchpoints(),
+            vec![(10, Access::Write), (11, Access::Read)]
+        );
+    }
+
+    #[test]
+    fn test_self_modification() {
+        let mut vm = Vm::from("1,9,10,3,2,3,11,0,99,30,40,50");
+        vm.detect_self_modification(true);
+
+        assert_eq!(vm.execute(), Ok(State::Halted));
+        assert_eq!(
+            vm.take_hits(),
+            vec![
+                Hit {
+                    step: 0,
+                    pointer: 0,
+                    address: 3,
+                    trigger: Trigger::SelfModification,
+                    value: w(70)
+                },
+                Hit {
+                    step: 1,
+                    pointer: 4,
+                    address: 0,
+                    trigger: Trigger::SelfModification,
+                    value: w(3500)
+                },
+            ]
+        );
+
+        let mut vm = Vm::from("1101,1,2,5,99,0");
+        vm.detect_self_modification(true);
+        vm.execute().unwrap();
+        assert!(vm.take_hits().is_empty());
+        assert!(vm.detects_self_modification());
+    }
+
+    #[test]
+    fn test_host_writes() {
+        let mut vm = Vm::from("1101,1,2,5,99,0");
+        vm.watch(5, Access::Write);
+        vm.detect_self_modification(true);
+        vm.enable_profile();
+        vm.execute().unwrap();
+        vm.take_hits();
+
+        vm.set(0, w(99));
+        vm.set(5, w(7));
+        assert!(vm.take_hits().is_empty());
+        assert_eq!(vm.take_profile().writes[&5], 1);
+    }
+}
@@ -0,0 +1,266 @@
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+#[cfg(not(feature = "bigint"))]
+pub type DefaultWord = i128;
+
+/// With the `bigint` feature every cell holds an arbitrary-precision integer, so arithmetic
+/// never overflows.
+#[cfg(feature = "bigint")]
+pub type DefaultWord = num_bigint::BigInt;
+
+/// A memory cell. Narrow words run faster; wide ones overflow later.
+pub trait Word:
+    Clone + Debug + Default + Display + FromStr + Ord + From<i32> + TryFrom<i128>
+{
+    fn to_i128(&self) -> Option<i128>;
+
+    /// Converts to a memory address, or `None` if negative or too large.
+    fn to_address(&self) -> Option<usize> {
+        self.to_i128().and_then(|value| usize::try_from(value).ok())
+    }
+
+    fn checked_add(&self, rhs: &Self) -> Option<Self>;
+
+    fn checked_mul(&self, rhs: &Self) -> Option<Self>;
+
+    fn wrapping_add(&self, rhs: &Self) -> Self;
+
+    fn wrapping_mul(&self, rhs: &Self) -> Self;
+
+    fn saturating_add(&self, rhs: &Self) -> Self;
+
+    fn saturating_mul(&self, rhs: &Self) -> Self;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::from(0)
+    }
+
+    fn is_negative(&self) -> bool {
+        *self < Self::from(0)
+    }
+}
+
+macro_rules! primitive_word {
+    ($($t:ty),*) => {
+        $(
+            impl Word for $t {
+                fn to_i128(&self) -> Option<i128> {
+                    Some(*self as i128)
+                }
+
+                fn checked_add(&self, rhs: &Self) -> Option<Self> {
+                    <$t>::checked_add(*self, *rhs)
+                }
+
+                fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+                    <$t>::checked_mul(*self, *rhs)
+                }
+
+                fn wrapping_add(&self, rhs: &Self) -> Self {
+                    <$t>::wrapping_add(*self, *rhs)
+                }
+
+                fn wrapping_mul(&self, rhs: &Self) -> Self {
+                    <$t>::wrapping_mul(*self, *rhs)
+                }
+
+                fn saturating_add(&self, rhs: &Self) -> Self {
+                    <$t>::saturating_add(*self, *rhs)
+                }
+
+                fn saturating_mul(&self, rhs: &Self) -> Self {
+                    <$t>::saturating_mul(*self, *rhs)
+                }
+            }
+        )*
+    };
+}
+
+primitive_word!(i32, i64, i128);
+
+#[cfg(feature = "bigint")]
+impl Word for num_bigint::BigInt {
+    fn to_i128(&self) -> Option<i128> {
+        num_traits::ToPrimitive::to_i128(self)
+    }
+
+    fn to_address(&self) -> Option<usize> {
+        num_traits::ToPrimitive::to_usize(self)
+    }
+
+    fn checked_add(&self, rhs: &Self) -> Option<Self> {
+        Some(self + rhs)
+    }
+
+    fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+        Some(self * rhs)
+    }
+
+    fn wrapping_add(&self, rhs: &Self) -> Self {
+        self + rhs
+    }
+
+    fn wrapping_mul(&self, rhs: &Self) -> Self {
+        self * rhs
+    }
+
+    fn saturating_add(&self, rhs: &Self) -> Self {
+        self + rhs
+    }
+
+    fn saturating_mul(&self, rhs: &Self) -> Self {
+        self * rhs
+    }
+
+    fn is_zero(&self) -> bool {
+        num_traits::Zero::is_zero(self)
+    }
+
+    fn is_negative(&self) -> bool {
+        num_traits::Signed::is_negative(self)
+    }
+}
+
+/// Serde helpers that store words as JSON numbers when they fit in an `i64` and as decimal
+/// strings otherwise, so trace, snapshot and replay files read the same with or without the
+/// `bigint` feature.
+pub(crate) mod decimal {
+    use super::Word;
+    use serde::de::{self, Deserialize, Deserializer, Visitor};
+    use serde::ser::{Serialize, Serializer};
+    use std::convert::TryFrom;
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    struct Ref<'a, W>(&'a W);
+
+    impl<W: Word> Serialize for Ref<'_, W> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self.0.to_i128().and_then(|value| i64::try_from(value).ok()) {
+                Some(value) => serializer.serialize_i64(value),
+                None => serializer.collect_str(self.0),
+            }
+        }
+    }
+
+    struct Owned<W>(W);
+
+    impl<'de, W: Word> Deserialize<'de> for Owned<W> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer
+                .deserialize_any(DecimalVisitor(PhantomData))
+                .map(Owned)
+        }
+    }
+
+    struct DecimalVisitor<W>(PhantomData<W>);
+
+    impl<W: Word> DecimalVisitor<W> {
+        fn convert<E: de::Error>(value: i128) -> Result<W, E> {
+            W::try_from(value).map_err(|_| E::custom(format!("{} does not fit in a word", value)))
+        }
+    }
+
+    impl<'de, W: Word> Visitor<'de> for DecimalVisitor<W> {
+        type Value = W;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an integer or a decimal string")
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<W, E> {
+            Self::convert(i128::from(value))
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<W, E> {
+            Self::convert(i128::from(value))
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<W, E> {
+            value
+                .parse()
+                .map_err(|_| E::custom(format!("invalid word: {}", value)))
+        }
+    }
+
+    pub(crate) fn serialize<W: Word, S: Serializer>(
+        value: &W,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Ref(value).serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, W: Word, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<W, D::Error> {
+        Owned::deserialize(deserializer).map(|Owned(value)| value)
+    }
+
+    pub(crate) mod option {
+        use super::{Owned, Ref, Word};
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        pub(crate) fn serialize<W: Word, S: Serializer>(
+            value: &Option<W>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            value.as_ref().map(Ref).serialize(serializer)
+        }
+
+        pub(crate) fn deserialize<'de, W: Word, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<W>, D::Error> {
+            Option::deserialize(deserializer).map(|value| value.map(|Owned(value)| value))
+        }
+    }
+
+    pub(crate) mod vec {
+        use super::{Owned, Ref, Word};
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub(crate) fn serialize<W: Word, S: Serializer>(
+            values: &[W],
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(values.iter().map(Ref))
+        }
+
+        pub(crate) fn deserialize<'de, W: Word, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<W>, D::Error> {
+            Vec::deserialize(deserializer)
+                .map(|values| values.into_iter().map(|Owned(value)| value).collect())
+        }
+    }
+
+    pub(crate) mod map {
+        use super::{Owned, Ref, Word};
+        use serde::{Deserialize, Deserializer, Serializer};
+        use std::collections::BTreeMap;
+
+        pub(crate) fn serialize<W: Word, S: Serializer>(
+            values: &BTreeMap<usize, W>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(values.iter().map(|(key, value)| (key, Ref(value))))
+        }
+
+        pub(crate) fn deserialize<'de, W: Word, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<BTreeMap<usize, W>, D::Error> {
+            BTreeMap::<usize, Owned<W>>::deserialize(deserializer).map(|values| {
+                values
+                    .into_iter()
+                    .map(|(key, Owned(value))| (key, value))
+                    .collect()
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn w(value: i128) -> DefaultWord {
+    DefaultWord::from(value)
+}
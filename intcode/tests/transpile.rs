@@ -0,0 +1,84 @@
+// The modules under `transpiled/` are `transpile` output for each program's `SOURCE`,
+// regenerated with `cargo run --bin transpile`.
+
+use intcode::transpile::{transpile, Hybrid};
+use intcode::{parse, State, Vm, VmError};
+
+macro_rules! transpiled {
+    ($name:ident, $source:expr) => {
+        mod $name {
+            pub const SOURCE: &str = $source;
+            pub const GENERATED: &str =
+                include_str!(concat!("transpiled/", stringify!($name), ".rs"));
+
+            include!(concat!("transpiled/", stringify!($name), ".rs"));
+        }
+    };
+}
+
+// Writes far past the end of the program, then jumps into the gap, which was never written.
+transpiled!(overrun, "1101,1,1,20,1105,1,10");
+
+// Outputs a copy of itself using relative mode.
+transpiled!(
+    quine,
+    "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99"
+);
+
+// AoC day 5: outputs 999, 1000 or 1001 as the input is below, equal to or above 8.
+transpiled!(
+    compare,
+    "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,\
+     1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99"
+);
+
+type Run = (Vec<i128>, Result<State<i128>, VmError<i128>>);
+
+fn run<F: FnMut() -> Result<State<i128>, VmError<i128>>>(mut execute: F) -> Run {
+    let mut outputs = Vec::new();
+    loop {
+        match execute() {
+            Ok(State::Output(value)) => outputs.push(value),
+            result => return (outputs, result),
+        }
+    }
+}
+
+fn interpreted(source: &str, input: &[i128]) -> Run {
+    let mut vm = Vm::<i128>::parse(source);
+    for &value in input {
+        vm.add_input(value);
+    }
+    run(|| vm.execute())
+}
+
+#[test]
+fn test_up_to_date() {
+    for (source, generated) in [
+        (overrun::SOURCE, overrun::GENERATED),
+        (quine::SOURCE, quine::GENERATED),
+        (compare::SOURCE, compare::GENERATED),
+    ] {
+        assert_eq!(transpile(&parse::<i128>(source)).unwrap(), generated);
+    }
+}
+
+#[test]
+fn test_matches_vm() {
+    let mut hybrid = Hybrid::new(overrun::Program::default());
+    assert_eq!(run(|| hybrid.execute()), interpreted(overrun::SOURCE, &[]));
+    assert_eq!(run(|| hybrid.execute()).1, Ok(State::Halted));
+
+    let mut hybrid = Hybrid::new(quine::Program::default());
+    assert_eq!(run(|| hybrid.execute()), interpreted(quine::SOURCE, &[]));
+
+    for input in [-3, 7, 8, 9, 100] {
+        let mut hybrid = Hybrid::new(compare::Program::default());
+        hybrid.add_input(input);
+        assert_eq!(
+            run(|| hybrid.execute()),
+            interpreted(compare::SOURCE, &[input])
+        );
+        assert!(hybrid.is_native());
+    }
+}
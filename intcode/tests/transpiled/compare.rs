@@ -0,0 +1,260 @@
+// Generated by intcode::transpile.
+
+use intcode::transpile::{Native, Unsupported};
+use intcode::State;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+static PROGRAM: [i128; 47] = [3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99];
+
+/// Writes further out are left to the interpreter's sparse memory.
+const MAX_MEMORY: usize = 1 << 24;
+
+#[derive(Clone, Debug)]
+pub struct Program {
+    memory: Vec<i128>,
+    written: Vec<bool>,
+    pointer: usize,
+    relative_base: i128,
+    steps: u64,
+    input: VecDeque<i128>,
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Program {
+            memory: PROGRAM.to_vec(),
+            written: vec![true; 47],
+            pointer: 0,
+            relative_base: 0,
+            steps: 0,
+            input: VecDeque::new(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Program {
+    pub fn get(&self, address: usize) -> i128 {
+        self.load(address)
+    }
+
+    pub fn set(&mut self, address: usize, value: i128) {
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, 0);
+            self.written.resize(address + 1, false);
+        }
+        self.memory[address] = value;
+        self.written[address] = true;
+    }
+
+    fn load(&self, address: usize) -> i128 {
+        self.memory.get(address).copied().unwrap_or(0)
+    }
+
+    fn address(&self, cell: usize) -> Option<usize> {
+        usize::try_from(self.load(cell)).ok()
+    }
+
+    fn relative(&self, cell: usize) -> Option<usize> {
+        usize::try_from(self.relative_base.checked_add(self.load(cell))?).ok()
+    }
+
+    fn store(&mut self, address: usize, value: i128) -> Option<()> {
+        if address >= MAX_MEMORY {
+            return None;
+        }
+        self.set(address, value);
+        Some(())
+    }
+
+    /// Runs the instruction at `pointer`, or returns `None` if only the interpreter can.
+    fn run(&mut self, pointer: usize) -> Option<Option<State<i128>>> {
+        match (pointer, self.load(pointer)) {
+            (0, 3) => {
+                let value = match self.input.front() {
+                    Some(&value) => value,
+                    None => return Some(Some(State::AwaitingInput)),
+                };
+                self.store(self.address(1)?, value)?;
+                self.input.pop_front();
+                self.pointer = 2;
+            }
+            (2, 1008) => {
+                let value = (self.load(self.address(3)?) == self.load(4)) as i128;
+                self.store(self.address(5)?, value)?;
+                self.pointer = 6;
+            }
+            (4, 8) => {
+                let value = (self.load(self.address(5)?) == self.load(self.address(6)?)) as i128;
+                self.store(self.address(7)?, value)?;
+                self.pointer = 8;
+            }
+            (6, 1005) => {
+                let value = self.load(self.address(7)?);
+                let target = self.load(8);
+                self.pointer = if value != 0 {
+                    usize::try_from(target).ok()?
+                } else {
+                    9
+                };
+            }
+            (9, 107) => {
+                let value = (self.load(10) < self.load(self.address(11)?)) as i128;
+                self.store(self.address(12)?, value)?;
+                self.pointer = 13;
+            }
+            (10, 8) => {
+                let value = (self.load(self.address(11)?) == self.load(self.address(12)?)) as i128;
+                self.store(self.address(13)?, value)?;
+                self.pointer = 14;
+            }
+            (13, 1006) => {
+                let value = self.load(self.address(14)?);
+                let target = self.load(15);
+                self.pointer = if value == 0 {
+                    usize::try_from(target).ok()?
+                } else {
+                    16
+                };
+            }
+            (16, 1106) => {
+                let value = self.load(17);
+                let target = self.load(18);
+                self.pointer = if value == 0 {
+                    usize::try_from(target).ok()?
+                } else {
+                    19
+                };
+            }
+            (22, 1002) => {
+                let value = self.load(self.address(23)?).checked_mul(self.load(24))?;
+                self.store(self.address(25)?, value)?;
+                self.pointer = 26;
+            }
+            (26, 4) => {
+                let value = self.load(self.address(27)?);
+                self.pointer = 28;
+                self.steps += 1;
+                return Some(Some(State::Output(value)));
+            }
+            (28, 1105) => {
+                let value = self.load(29);
+                let target = self.load(30);
+                self.pointer = if value != 0 {
+                    usize::try_from(target).ok()?
+                } else {
+                    31
+                };
+            }
+            (29, 1) => {
+                let value = self.load(self.address(30)?).checked_add(self.load(self.address(31)?))?;
+                self.store(self.address(32)?, value)?;
+                self.pointer = 33;
+            }
+            (31, 104) => {
+                let value = self.load(32);
+                self.pointer = 33;
+                self.steps += 1;
+                return Some(Some(State::Output(value)));
+            }
+            (33, 1105) => {
+                let value = self.load(34);
+                let target = self.load(35);
+                self.pointer = if value != 0 {
+                    usize::try_from(target).ok()?
+                } else {
+                    36
+                };
+            }
+            (34, 1) => {
+                let value = self.load(self.address(35)?).checked_add(self.load(self.address(36)?))?;
+                self.store(self.address(37)?, value)?;
+                self.pointer = 38;
+            }
+            (36, 1101) => {
+                let value = self.load(37).checked_add(self.load(38))?;
+                self.store(self.address(39)?, value)?;
+                self.pointer = 40;
+            }
+            (38, 1) => {
+                let value = self.load(self.address(39)?).checked_add(self.load(self.address(40)?))?;
+                self.store(self.address(41)?, value)?;
+                self.pointer = 42;
+            }
+            (40, 4) => {
+                let value = self.load(self.address(41)?);
+                self.pointer = 42;
+                self.steps += 1;
+                return Some(Some(State::Output(value)));
+            }
+            (42, 1105) => {
+                let value = self.load(43);
+                let target = self.load(44);
+                self.pointer = if value != 0 {
+                    usize::try_from(target).ok()?
+                } else {
+                    45
+                };
+            }
+            (43, 1) => {
+                let value = self.load(self.address(44)?).checked_add(self.load(self.address(45)?))?;
+                self.store(self.address(46)?, value)?;
+                self.pointer = 47;
+            }
+            (46, 99) => {
+                self.steps += 1;
+                return Some(Some(State::Halted));
+            }
+            _ => return None,
+        }
+
+        self.steps += 1;
+        Some(None)
+    }
+}
+
+impl Native for Program {
+    fn add_input(&mut self, value: i128) {
+        self.input.push_back(value);
+    }
+
+    fn step(&mut self) -> Result<Option<State<i128>>, Unsupported> {
+        let pointer = self.pointer;
+        self.run(pointer).ok_or(Unsupported { pointer })
+    }
+
+    fn execute(&mut self) -> Result<State<i128>, Unsupported> {
+        loop {
+            let pointer = self.pointer;
+            match self.run(pointer) {
+                Some(Some(state)) => return Ok(state),
+                Some(None) => {}
+                None => return Err(Unsupported { pointer }),
+            }
+        }
+    }
+
+    fn cells(&self) -> Vec<(usize, i128)> {
+        (0..self.memory.len())
+            .filter(|&address| self.written[address])
+            .map(|address| (address, self.memory[address]))
+            .collect()
+    }
+
+    fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    fn relative_base(&self) -> i128 {
+        self.relative_base
+    }
+
+    fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    fn input(&self) -> &VecDeque<i128> {
+        &self.input
+    }
+}
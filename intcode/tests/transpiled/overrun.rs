@@ -0,0 +1,149 @@
+// Generated by intcode::transpile.
+
+use intcode::transpile::{Native, Unsupported};
+use intcode::State;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+static PROGRAM: [i128; 7] = [1101, 1, 1, 20, 1105, 1, 10];
+
+/// Writes further out are left to the interpreter's sparse memory.
+const MAX_MEMORY: usize = 1 << 24;
+
+#[derive(Clone, Debug)]
+pub struct Program {
+    memory: Vec<i128>,
+    written: Vec<bool>,
+    pointer: usize,
+    relative_base: i128,
+    steps: u64,
+    input: VecDeque<i128>,
+}
+
+impl Default for Program {
+    fn default() -> Self {
+        Program {
+            memory: PROGRAM.to_vec(),
+            written: vec![true; 7],
+            pointer: 0,
+            relative_base: 0,
+            steps: 0,
+            input: VecDeque::new(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Program {
+    pub fn get(&self, address: usize) -> i128 {
+        self.load(address)
+    }
+
+    pub fn set(&mut self, address: usize, value: i128) {
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, 0);
+            self.written.resize(address + 1, false);
+        }
+        self.memory[address] = value;
+        self.written[address] = true;
+    }
+
+    fn load(&self, address: usize) -> i128 {
+        self.memory.get(address).copied().unwrap_or(0)
+    }
+
+    fn address(&self, cell: usize) -> Option<usize> {
+        usize::try_from(self.load(cell)).ok()
+    }
+
+    fn relative(&self, cell: usize) -> Option<usize> {
+        usize::try_from(self.relative_base.checked_add(self.load(cell))?).ok()
+    }
+
+    fn store(&mut self, address: usize, value: i128) -> Option<()> {
+        if address >= MAX_MEMORY {
+            return None;
+        }
+        self.set(address, value);
+        Some(())
+    }
+
+    /// Runs the instruction at `pointer`, or returns `None` if only the interpreter can.
+    fn run(&mut self, pointer: usize) -> Option<Option<State<i128>>> {
+        match (pointer, self.load(pointer)) {
+            (0, 1101) => {
+                let value = self.load(1).checked_add(self.load(2))?;
+                self.store(self.address(3)?, value)?;
+                self.pointer = 4;
+            }
+            (1, 1) => {
+                let value = self.load(self.address(2)?).checked_add(self.load(self.address(3)?))?;
+                self.store(self.address(4)?, value)?;
+                self.pointer = 5;
+            }
+            (2, 1) => {
+                let value = self.load(self.address(3)?).checked_add(self.load(self.address(4)?))?;
+                self.store(self.address(5)?, value)?;
+                self.pointer = 6;
+            }
+            (4, 1105) => {
+                let value = self.load(5);
+                let target = self.load(6);
+                self.pointer = if value != 0 {
+                    usize::try_from(target).ok()?
+                } else {
+                    7
+                };
+            }
+            _ => return None,
+        }
+
+        self.steps += 1;
+        Some(None)
+    }
+}
+
+impl Native for Program {
+    fn add_input(&mut self, value: i128) {
+        self.input.push_back(value);
+    }
+
+    fn step(&mut self) -> Result<Option<State<i128>>, Unsupported> {
+        let pointer = self.pointer;
+        self.run(pointer).ok_or(Unsupported { pointer })
+    }
+
+    fn execute(&mut self) -> Result<State<i128>, Unsupported> {
+        loop {
+            let pointer = self.pointer;
+            match self.run(pointer) {
+                Some(Some(state)) => return Ok(state),
+                Some(None) => {}
+                None => return Err(Unsupported { pointer }),
+            }
+        }
+    }
+
+    fn cells(&self) -> Vec<(usize, i128)> {
+        (0..self.memory.len())
+            .filter(|&address| self.written[address])
+            .map(|address| (address, self.memory[address]))
+            .collect()
+    }
+
+    fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    fn relative_base(&self) -> i128 {
+        self.relative_base
+    }
+
+    fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    fn input(&self) -> &VecDeque<i128> {
+        &self.input
+    }
+}